@@ -0,0 +1,255 @@
+use crate::model;
+use crate::position::LineIndex;
+use std::collections::BTreeMap;
+use vrl::diagnostic::Span;
+use vrl::parser::ast::{
+    Assignment, AssignmentOp, AssignmentTarget, Container, Expr, FunctionCall, Literal, Node,
+    Predicate, Query, QueryTarget, RootExpr, Unary,
+};
+use vrl::path::PathPrefix;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Access {
+    Read,
+    Assignment,
+    Deletion,
+}
+
+/// Paths and their locations for one kind of access, kept sorted by path
+type Accesses = BTreeMap<String, Vec<model::AccessLocation>>;
+
+#[derive(Default)]
+struct AccessMaps {
+    reads: Accesses,
+    assignments: Accesses,
+    deletions: Accesses,
+}
+
+impl AccessMaps {
+    fn record(&mut self, access: Access, path: String, location: model::AccessLocation) {
+        let accesses = match access {
+            Access::Read => &mut self.reads,
+            Access::Assignment => &mut self.assignments,
+            Access::Deletion => &mut self.deletions,
+        };
+        accesses.entry(path).or_default().push(location);
+    }
+
+    fn into_set(self) -> model::AccessSet {
+        let list = |accesses: Accesses| {
+            accesses
+                .into_iter()
+                .map(|(path, mut locations)| {
+                    locations.sort_by_key(|location| location.span);
+                    model::AccessedPath { path, locations }
+                })
+                .collect()
+        };
+        model::AccessSet {
+            reads: list(self.reads),
+            assignments: list(self.assignments),
+            deletions: list(self.deletions),
+        }
+    }
+}
+
+/**
+ * Lists the event paths, metadata paths and secrets a program accesses, from its syntax tree.
+ *
+ * VRL only changes its target through assignments and `del()`, so a path is assigned when it
+ * is an assignment target (including `ok, err = ...`), deleted when it is the first argument
+ * of `del()`, and read otherwise. Paths of local variables (`x.foo`) are not part of the target.
+ * Secrets are only known when the name passed to the secret functions is a string literal.
+ */
+pub fn analyze(source: &str) -> model::ProgramAnalysis {
+    let mut analyzer = Analyzer {
+        source,
+        line_index: LineIndex::new(source),
+        event: AccessMaps::default(),
+        metadata: AccessMaps::default(),
+        secrets: AccessMaps::default(),
+    };
+    if let Ok(program) = vrl::parser::parse(source) {
+        for root in program.0 {
+            if let RootExpr::Expr(expr) = root.into_inner() {
+                analyzer.expr(expr);
+            }
+        }
+    }
+
+    model::ProgramAnalysis {
+        event: analyzer.event.into_set(),
+        metadata: analyzer.metadata.into_set(),
+        secrets: analyzer.secrets.into_set(),
+    }
+}
+
+struct Analyzer<'a> {
+    source: &'a str,
+    line_index: LineIndex<'a>,
+    event: AccessMaps,
+    metadata: AccessMaps,
+    secrets: AccessMaps,
+}
+
+impl Analyzer<'_> {
+    fn location(&self, span: Span) -> model::AccessLocation {
+        model::AccessLocation {
+            span: (span.start(), span.end()),
+            start: self.line_index.position(span.start()),
+            end: self.line_index.position(span.end()),
+        }
+    }
+
+    fn expr(&mut self, expr: Node<Expr>) {
+        let (span, expr) = expr.take();
+        match expr {
+            Expr::Literal(_) | Expr::Variable(_) => {}
+            Expr::Container(container) => self.container(container.into_inner()),
+            Expr::IfStatement(statement) => {
+                let statement = statement.into_inner();
+                match statement.predicate.into_inner() {
+                    Predicate::One(expr) => self.expr(*expr),
+                    Predicate::Many(exprs) => self.exprs(exprs),
+                }
+                let blocks = std::iter::once(statement.if_node).chain(statement.else_node);
+                for block in blocks {
+                    self.exprs(block.into_inner().0);
+                }
+            }
+            Expr::Op(op) => {
+                let op = op.into_inner();
+                self.expr(*op.0);
+                self.expr(*op.2);
+            }
+            Expr::Assignment(assignment) => match assignment.into_inner() {
+                Assignment::Single { target, op, expr } => {
+                    self.assignment_target(target, &op);
+                    self.expr(*expr);
+                }
+                Assignment::Infallible { ok, err, op, expr } => {
+                    self.assignment_target(ok, &op);
+                    self.assignment_target(err, &op);
+                    self.expr(*expr);
+                }
+            },
+            Expr::Query(query) => self.query(span, query.into_inner(), Access::Read),
+            Expr::FunctionCall(call) => self.call(call.into_inner()),
+            Expr::Unary(unary) => match unary.into_inner() {
+                Unary::Not(not) => self.expr(*not.into_inner().take().1),
+            },
+            Expr::Abort(abort) => {
+                if let Some(message) = abort.into_inner().message {
+                    self.expr(*message);
+                }
+            }
+            Expr::Return(ret) => self.expr(*ret.into_inner().expr),
+        }
+    }
+
+    fn exprs(&mut self, exprs: Vec<Node<Expr>>) {
+        for expr in exprs {
+            self.expr(expr);
+        }
+    }
+
+    fn container(&mut self, container: Container) {
+        match container {
+            Container::Group(group) => self.expr(group.into_inner().into_inner()),
+            Container::Block(block) => self.exprs(block.into_inner().0),
+            Container::Array(array) => self.exprs(array.into_inner().into_iter().collect()),
+            Container::Object(object) => {
+                for (_, expr) in object.into_inner().into_iter() {
+                    self.expr(expr);
+                }
+            }
+        }
+    }
+
+    /// Records the path of an event or metadata query, as written in the program
+    fn query(&mut self, span: Span, query: Query, access: Access) {
+        match query.target.into_inner() {
+            QueryTarget::External(prefix) => {
+                let path = self.source[span.start()..span.end()].to_owned();
+                let location = self.location(span);
+                let maps = match prefix {
+                    PathPrefix::Event => &mut self.event,
+                    PathPrefix::Metadata => &mut self.metadata,
+                };
+                maps.record(access, path, location);
+            }
+            QueryTarget::FunctionCall(call) => self.call(call),
+            QueryTarget::Container(container) => self.container(container),
+            QueryTarget::Internal(_) => {}
+        }
+    }
+
+    /// A merge (`|=`) reads the target as well as assigning it
+    fn assignment_target(&mut self, target: Node<AssignmentTarget>, op: &AssignmentOp) {
+        let (span, target) = target.take();
+        if let AssignmentTarget::Query(query) = target {
+            if matches!(op, AssignmentOp::Merge) {
+                self.query(span, query.clone(), Access::Read);
+            }
+            self.query(span, query, Access::Assignment);
+        }
+    }
+
+    fn call(&mut self, call: FunctionCall) {
+        let mut arguments = call
+            .arguments
+            .into_iter()
+            .map(|argument| argument.into_inner());
+        match call.ident.inner().as_ref() {
+            "del" => {
+                if let Some(first) = arguments.next() {
+                    match first.expr.take() {
+                        (span, Expr::Query(query)) => {
+                            self.query(span, query.into_inner(), Access::Deletion)
+                        }
+                        (span, expr) => self.expr(Node::new(span, expr)),
+                    }
+                }
+            }
+            identifier => {
+                let access = match identifier {
+                    "get_secret" => Some(Access::Read),
+                    "set_secret" => Some(Access::Assignment),
+                    "remove_secret" => Some(Access::Deletion),
+                    _ => None,
+                };
+                if let Some(access) = access {
+                    if let Some(first) = arguments.next() {
+                        self.secret(first.expr, access);
+                    }
+                }
+            }
+        }
+        for argument in arguments {
+            self.expr(argument.expr);
+        }
+        if let Some(closure) = call.closure {
+            self.exprs(closure.into_inner().block.into_inner().0);
+        }
+    }
+
+    /// Records the secret name passed to a secret function, when it is a string literal
+    fn secret(&mut self, name: Node<Expr>, access: Access) {
+        let (span, name) = name.take();
+        let literal = match &name {
+            Expr::Literal(literal) => match literal.inner() {
+                Literal::String(template) => template.as_literal_string().map(str::to_owned),
+                Literal::RawString(name) => Some(name.clone()),
+                _ => None,
+            },
+            _ => None,
+        };
+        match literal {
+            Some(literal) => {
+                let location = self.location(span);
+                self.secrets.record(access, literal, location);
+            }
+            None => self.expr(Node::new(span, name)),
+        }
+    }
+}
@@ -0,0 +1,886 @@
+//! The compile, check and execute logic behind the JavaScript exports, with plain Rust
+//! types so it can be used from Rust services and from the `verel` binary.
+
+use crate::model;
+use crate::target::ExecutionTarget;
+use crate::timezone::ExecutionTimeZone;
+use crate::{
+    analysis, cache, codec, determinism, host, language, limits, position, render, sandbox, schema,
+    secret, testing, timezone, trace,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::cell::OnceCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use vrl::compiler::runtime::{Runtime, Terminate};
+use vrl::compiler::state::RuntimeState;
+use vrl::compiler::{compile_with_state, CompileConfig, TypeState};
+use vrl::compiler::{Context, TimeZone};
+use vrl::prelude::{ExpressionError, Function};
+use vrl::value::Kind;
+use vrl::value::Value;
+
+/// The VRL stdlib and the functions verel adds to it, without the host functions
+pub(crate) fn builtin_functions() -> Vec<Box<dyn Function>> {
+    let mut functions = timezone::replace_functions(vrl::stdlib::all());
+    functions.extend(secret::functions());
+    functions
+}
+
+pub fn compile(input: &model::CompilationInput) -> model::CompilationOutput {
+    let mut functions = builtin_functions();
+    functions.extend(host::functions());
+    let mut functions = sandbox::filter(functions, &input.sandbox);
+    if input.sandbox.deterministic {
+        functions = determinism::fix_clock(functions);
+    }
+    let functions = limits::instrument(functions);
+
+    let config = CompileConfig::default();
+
+    let line_index = position::LineIndex::new(&input.program);
+    let forbidden = sandbox::check(&input.program, &input.sandbox);
+    if !forbidden.is_empty() {
+        return Err(model::ErrorCompilationOutput {
+            errors: forbidden
+                .into_iter()
+                .map(|diagnostic| diagnostic.locate(&line_index))
+                .collect(),
+        });
+    }
+
+    let compiled = compile_with_state(&input.program, &functions, &input.state, config);
+    match compiled {
+        Ok(res) => Ok(model::SuccessCompilationOutput {
+            program: limits::meter_closures(&input.program, &functions, &input.state)
+                .unwrap_or(res.program),
+            warnings: res
+                .warnings
+                .iter()
+                .map(|d: &vrl::diagnostic::Diagnostic| {
+                    model::CompilationDiagnostic::from(d).locate(&line_index)
+                })
+                .collect(),
+        }),
+        Err(diagnostics) => Err(model::ErrorCompilationOutput {
+            errors: diagnostics
+                .iter()
+                .map(|d: &vrl::diagnostic::Diagnostic| {
+                    model::CompilationDiagnostic::from(d).locate(&line_index)
+                })
+                .collect(),
+        }),
+    }
+}
+
+/// Compiles through the program cache, keyed by the source and the options the input was built from
+fn compile_cached(
+    input: &model::CompilationInput,
+    options: &impl Serialize,
+) -> Rc<model::CompilationOutput> {
+    let key = cache::CacheKey::new(&input.program, options);
+    cache::get_or_compile(key, || compile(input))
+}
+
+/// Compiles a program to check it, with the schemas and sandbox of the check options
+fn compile_checked(input: &model::CheckInput) -> Result<Rc<model::CompilationOutput>, String> {
+    let state = schema::type_state(
+        input.options.event_schema.as_ref(),
+        input.options.metadata_schema.as_ref(),
+    )?;
+    let options = &input.options;
+    Ok(compile_cached(
+        &model::CompilationInput {
+            program: input.program.clone(),
+            state,
+            sandbox: options.sandbox.clone(),
+        },
+        &(
+            &options.event_schema,
+            &options.metadata_schema,
+            &options.sandbox,
+        ),
+    ))
+}
+
+/**
+ * Applies the diagnostic policy of the check options: promotes warnings to errors, then
+ * leaves out suppressed warnings and the ones below the minimum severity. Errors are always kept.
+ */
+fn apply_policy(
+    options: &model::CheckOptions,
+    warnings: Vec<model::CompilationDiagnostic>,
+    mut errors: Vec<model::CompilationDiagnostic>,
+) -> (
+    Vec<model::CompilationDiagnostic>,
+    Vec<model::CompilationDiagnostic>,
+) {
+    let mut kept = vec![];
+    for mut warning in warnings {
+        if options.promote.contains(&warning.code) {
+            warning.severity = "error".to_owned();
+            errors.push(warning);
+        } else if !options.suppress.contains(&warning.code)
+            && model::DiagnosticSeverity::parse(&warning.severity) >= options.min_severity
+        {
+            kept.push(warning);
+        }
+    }
+    (kept, errors)
+}
+
+pub fn check(input: &model::CheckInput) -> Result<model::CheckOutput, String> {
+    let compile_output = compile_checked(input)?;
+    let output = model::CheckOutput::from(&*compile_output);
+    let (warnings, errors) = apply_policy(&input.options, output.warnings, output.errors);
+    Ok(model::CheckOutput { warnings, errors })
+}
+
+pub fn typecheck(input: &model::CheckInput) -> Result<model::TypecheckOutput, String> {
+    let compile_output = compile_checked(input)?;
+
+    let output = match &*compile_output {
+        Ok(output) => output,
+        Err(err) => {
+            return Ok(model::TypecheckOutput {
+                warnings: vec![],
+                errors: err.errors.clone(),
+                types: None,
+            })
+        }
+    };
+
+    let inferred = |kind: &Kind| model::InferredType {
+        kind: kind.to_string(),
+        schema: schema::from_kind(kind),
+    };
+    let type_info = output.program.final_type_info();
+    let info = output.program.info();
+    let (warnings, errors) = apply_policy(&input.options, output.warnings.clone(), vec![]);
+    Ok(model::TypecheckOutput {
+        warnings,
+        errors,
+        types: Some(model::InferredTypes {
+            event: inferred(type_info.state.external.target_kind()),
+            metadata: inferred(type_info.state.external.metadata_kind()),
+            result: inferred(type_info.result.kind()),
+            fallible: info.fallible,
+            abortable: info.abortable,
+        }),
+    })
+}
+
+pub fn analyze(input: &model::CheckInput) -> Result<model::AnalysisOutput, String> {
+    let compile_output = compile_checked(input)?;
+
+    Ok(match &*compile_output {
+        Ok(output) => {
+            let (warnings, errors) = apply_policy(&input.options, output.warnings.clone(), vec![]);
+            model::AnalysisOutput {
+                warnings,
+                errors,
+                analysis: Some(analysis::analyze(&input.program)),
+            }
+        }
+        Err(err) => model::AnalysisOutput {
+            warnings: vec![],
+            errors: err.errors.clone(),
+            analysis: None,
+        },
+    })
+}
+
+pub fn execute(input: &model::ExecutionInput) -> model::ExecutionOutput {
+    let source = model::CompilationInput {
+        program: input.program.clone(),
+        state: TypeState::default(),
+        sandbox: input.options.sandbox.clone(),
+    };
+    let compile_output = compile_cached(&source, &input.options.sandbox);
+
+    let program: &model::CompiledProgram = match &*compile_output {
+        Ok(output) => &output.program,
+        Err(err) => return Err(model::ErrorExecutionOutput::CompilationError(err.clone())),
+    };
+
+    let timezone = resolve_timezone(&input.options, &input.options.sandbox)?;
+    let clock = resolve_clock(&input.options, &input.options.sandbox)?;
+    let (event, metadata) = decode_input(input.event.clone(), &input.options)
+        .map_err(model::ErrorExecutionOutput::InvalidInput)?;
+    let mut runtime = Runtime::default();
+    run(
+        program,
+        &TracedStatements::new(source),
+        &mut runtime,
+        (event, metadata),
+        &input.options,
+        &timezone,
+        clock,
+    )
+}
+
+pub fn execute_batch(input: &model::BatchExecutionInput) -> model::BatchExecutionOutput {
+    let source = model::CompilationInput {
+        program: input.program.clone(),
+        state: TypeState::default(),
+        sandbox: input.options.sandbox.clone(),
+    };
+    let compile_output = compile_cached(&source, &input.options.sandbox);
+
+    let program: &model::CompiledProgram = match &*compile_output {
+        Ok(output) => &output.program,
+        Err(err) => return Err(model::ErrorExecutionOutput::CompilationError(err.clone())),
+    };
+
+    let timezone = resolve_timezone(&input.options, &input.options.sandbox)?;
+    let clock = resolve_clock(&input.options, &input.options.sandbox)?;
+    let statements = TracedStatements::new(source);
+    let mut runtime = Runtime::default();
+    let mut items = vec![];
+    for event in input.events.iter() {
+        let (event, metadata) = match decode_input(event.clone(), &input.options) {
+            Ok(decoded) => decoded,
+            Err(message) => {
+                items.push(model::BatchExecutionItem::InvalidInput { message });
+                continue;
+            }
+        };
+        let output = run(
+            program,
+            &statements,
+            &mut runtime,
+            (event, metadata),
+            &input.options,
+            &timezone,
+            clock,
+        );
+        items.push(match output {
+            Ok(output) => model::BatchExecutionItem::Success(output),
+            Err(model::ErrorExecutionOutput::Termination(termination)) => {
+                model::BatchExecutionItem::Termination(*termination)
+            }
+            Err(err) => return Err(err),
+        });
+    }
+    Ok(items)
+}
+
+pub fn test(input: &model::TestInput) -> model::TestOutput {
+    let source = model::CompilationInput {
+        program: input.program.clone(),
+        state: TypeState::default(),
+        sandbox: input.options.sandbox.clone(),
+    };
+    let compile_output = compile_cached(&source, &input.options.sandbox);
+
+    let program: &model::CompiledProgram = match &*compile_output {
+        Ok(output) => &output.program,
+        Err(err) => return Err(model::ErrorExecutionOutput::CompilationError(err.clone())),
+    };
+
+    let timezone = resolve_timezone(&input.options, &input.options.sandbox)?;
+    let clock = resolve_clock(&input.options, &input.options.sandbox)?;
+    let statements = TracedStatements::new(source);
+    let mut runtime = Runtime::default();
+    let mut cases = vec![];
+    for (index, case) in input.cases.iter().enumerate() {
+        let mut options = input.options.clone();
+        if case.metadata.is_some() {
+            options.metadata = case.metadata.clone();
+        }
+        let name = case
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("case {}", index + 1));
+        let (event, metadata) = match decode_input(case.event.clone(), &options) {
+            Ok(decoded) => decoded,
+            Err(message) => {
+                cases.push(testing::invalid_input(name, message));
+                continue;
+            }
+        };
+        let outcome = match run(
+            program,
+            &statements,
+            &mut runtime,
+            (event, metadata),
+            &options,
+            &timezone,
+            clock,
+        ) {
+            Ok(output) => Ok(output),
+            Err(model::ErrorExecutionOutput::Termination(termination)) => Err(*termination),
+            Err(err) => return Err(err),
+        };
+        cases.push(testing::evaluate(case, name, outcome));
+    }
+
+    let passed = cases.iter().filter(|case| case.passed).count();
+    Ok(model::TestReport {
+        passed,
+        failed: cases.len() - passed,
+        cases,
+    })
+}
+
+fn language_context(input: &model::LanguageInput) -> Result<language::LanguageContext, String> {
+    let state = schema::type_state(
+        input.options.event_schema.as_ref(),
+        input.options.metadata_schema.as_ref(),
+    )?;
+
+    let mut functions = builtin_functions();
+    functions.extend(host::functions());
+    functions.retain(|function| sandbox::is_allowed(&input.options.sandbox, function.identifier()));
+
+    // programs being edited are often incomplete, in which case only the initial state is known
+    let compile_output = compile(&model::CompilationInput {
+        program: input.program.clone(),
+        state: state.clone(),
+        sandbox: input.options.sandbox.clone(),
+    });
+    let state = match compile_output {
+        Ok(output) => output.program.final_type_info().state,
+        Err(_) => state,
+    };
+
+    Ok(language::LanguageContext {
+        functions,
+        target_kind: state.external.target_kind().clone(),
+        metadata_kind: state.external.metadata_kind().clone(),
+        state,
+    })
+}
+
+pub fn complete(input: &model::LanguageInput) -> Result<model::CompletionOutput, String> {
+    let context = language_context(input)?;
+    Ok(language::complete(&input.program, input.offset, &context))
+}
+
+pub fn hover(input: &model::LanguageInput) -> Result<Option<model::HoverOutput>, String> {
+    let context = language_context(input)?;
+    Ok(language::hover(&input.program, input.offset, &context))
+}
+
+pub fn format(program: &str) -> model::FormatOutput {
+    match vrl::parser::parse(program) {
+        Ok(_) => model::FormatOutput {
+            formatted: Some(crate::format::format(program)),
+            errors: vec![],
+        },
+        Err(err) => {
+            let diagnostic = vrl::diagnostic::Diagnostic::from(
+                Box::new(err) as Box<dyn vrl::diagnostic::DiagnosticMessage>
+            );
+            let line_index = position::LineIndex::new(program);
+            model::FormatOutput {
+                formatted: None,
+                errors: vec![model::CompilationDiagnostic::from(&diagnostic).locate(&line_index)],
+            }
+        }
+    }
+}
+
+/// Resolves the timezone option, with the sandbox the program was compiled with
+fn resolve_timezone(
+    options: &model::ExecutionOptions,
+    sandbox: &model::SandboxOptions,
+) -> Result<ExecutionTimeZone, model::ErrorExecutionOutput> {
+    match &options.timezone {
+        Some(name) => timezone::resolve(name).map_err(model::ErrorExecutionOutput::InvalidOption),
+        // the local timezone depends on the host
+        None if sandbox.deterministic => {
+            timezone::resolve("UTC").map_err(model::ErrorExecutionOutput::InvalidOption)
+        }
+        None => Ok(ExecutionTimeZone::Vrl(TimeZone::default())),
+    }
+}
+
+/// Resolves the clock option, which deterministic programs require since `now()` returns it
+fn resolve_clock(
+    options: &model::ExecutionOptions,
+    sandbox: &model::SandboxOptions,
+) -> Result<Option<DateTime<Utc>>, model::ErrorExecutionOutput> {
+    match &options.clock {
+        Some(clock) => DateTime::parse_from_rfc3339(clock)
+            .map(|clock| Some(clock.with_timezone(&Utc)))
+            .map_err(|err| {
+                model::ErrorExecutionOutput::InvalidOption(format!(
+                    "invalid clock \"{}\": {}",
+                    clock, err
+                ))
+            }),
+        None if sandbox.deterministic => Err(model::ErrorExecutionOutput::InvalidOption(
+            "deterministic programs require the \"clock\" execution option".to_owned(),
+        )),
+        None => Ok(None),
+    }
+}
+
+fn run(
+    program: &model::CompiledProgram,
+    statements: &TracedStatements,
+    runtime: &mut Runtime,
+    (event, metadata): (Value, Option<Value>),
+    options: &model::ExecutionOptions,
+    timezone: &ExecutionTimeZone,
+    clock: Option<DateTime<Utc>>,
+) -> model::ExecutionOutput {
+    let mut target = ExecutionTarget::new(
+        event,
+        metadata.unwrap_or_else(|| Value::Object(BTreeMap::new())),
+        options.secrets.as_ref(),
+    );
+
+    determinism::set_clock(clock);
+    timezone::set_current(*timezone);
+    let timezone = &timezone.vrl();
+    limits::start(&options.limits);
+    let (resolved, trace) = if options.trace {
+        let (resolved, steps) = resolve_traced(statements, &mut target, timezone);
+        (resolved, Some(steps))
+    } else {
+        (runtime.resolve(&mut target, program, timezone), None)
+    };
+    runtime.clear();
+    if let Some(exceeded) = limits::finish() {
+        return Err(model::ErrorExecutionOutput::Termination(Box::new(
+            model::ExecutionTermination::new(model::ExecutionTerminationKind::Limit, exceeded),
+        )));
+    }
+
+    let encode = |value: Value| match options.encoding {
+        model::ValueEncoding::Json => value,
+        model::ValueEncoding::Tagged => codec::encode(value),
+    };
+    let trace = trace.map(|steps| {
+        steps
+            .into_iter()
+            .map(|step| model::TraceStep {
+                value: step.value.map(encode),
+                assignments: step
+                    .assignments
+                    .into_iter()
+                    .map(|assignment| model::TraceAssignment {
+                        value: assignment.value.map(encode),
+                        ..assignment
+                    })
+                    .collect(),
+                ..step
+            })
+            .collect()
+    });
+
+    let res = match resolved {
+        Ok(res) => res,
+        Err(terminate) => {
+            let termination = model::ExecutionTermination::from(&terminate);
+            return Err(model::ErrorExecutionOutput::Termination(Box::new(
+                model::ExecutionTermination {
+                    value: termination.value.map(encode),
+                    trace,
+                    ..termination
+                },
+            )));
+        }
+    };
+
+    let within_limits = limits::check_output(&target.value.value, &options.limits)
+        .and_then(|_| limits::check_output(&res, &options.limits));
+    if let Err(exceeded) = within_limits {
+        return Err(model::ErrorExecutionOutput::Termination(Box::new(
+            model::ExecutionTermination::new(model::ExecutionTerminationKind::Limit, exceeded),
+        )));
+    }
+
+    Ok(model::SuccessExecutionOutput {
+        secrets: target.secrets(),
+        event: encode(target.value.value),
+        metadata: encode(target.value.metadata),
+        result: encode(res),
+        trace,
+    })
+}
+
+/**
+ * Decodes the event and the metadata option when they use the tagged encoding. Errors are
+ * about one event, so executions on many events report them for that event only.
+ */
+fn decode_input(
+    event: model::ExecutionEvent,
+    options: &model::ExecutionOptions,
+) -> Result<(Value, Option<Value>), String> {
+    match options.encoding {
+        model::ValueEncoding::Json => Ok((event, options.metadata.clone())),
+        model::ValueEncoding::Tagged => {
+            let event = codec::decode(event).map_err(|err| format!("event {}", err))?;
+            let metadata = options
+                .metadata
+                .clone()
+                .map(codec::decode)
+                .transpose()
+                .map_err(|err| format!("metadata {}", err))?;
+            Ok((event, metadata))
+        }
+    }
+}
+
+type CompiledStatements = Vec<((usize, usize), model::CompiledProgram)>;
+
+/// The top-level statements of a program, compiled on the first traced execution only
+struct TracedStatements {
+    source: model::CompilationInput,
+    compiled: OnceCell<Result<CompiledStatements, String>>,
+}
+
+impl TracedStatements {
+    fn new(source: model::CompilationInput) -> Self {
+        TracedStatements {
+            source,
+            compiled: OnceCell::new(),
+        }
+    }
+
+    /**
+     * Compiles the statements one at a time, carrying the type state over. Each one is preceded
+     * by as many spaces as the source before it, so that the spans of its errors, and the
+     * positions quoted in their messages, point into the whole program.
+     */
+    fn compiled(&self) -> &Result<CompiledStatements, String> {
+        self.compiled.get_or_init(|| {
+            let mut state = self.source.state.clone();
+            let mut statements = vec![];
+            for span in trace::statements(&self.source.program) {
+                let program = format!(
+                    "{}{}",
+                    " ".repeat(span.0),
+                    &self.source.program[span.0..span.1]
+                );
+                let statement = compile(&model::CompilationInput {
+                    program,
+                    state: state.clone(),
+                    sandbox: self.source.sandbox.clone(),
+                })
+                .map_err(|err| format!("unable to trace statement: {}", err.summary()))?
+                .program;
+                state = statement.final_type_info().state;
+                statements.push((span, statement));
+            }
+            Ok(statements)
+        })
+    }
+}
+
+/**
+ * Resolves the top-level statements one at a time, sharing the runtime state so local
+ * variables carry over, and records the value of each statement and the paths it assigned.
+ * Like `Runtime::resolve`, a `return` ends the program with its value.
+ */
+fn resolve_traced(
+    statements: &TracedStatements,
+    target: &mut ExecutionTarget,
+    timezone: &TimeZone,
+) -> (Result<Value, Terminate>, Vec<model::TraceStep>) {
+    let line_index = position::LineIndex::new(&statements.source.program);
+    let mut state = RuntimeState::default();
+    let mut steps = vec![];
+    let mut result = Value::Null;
+
+    let compiled = match statements.compiled() {
+        Ok(compiled) => compiled,
+        Err(message) => {
+            let error = ExpressionError::from(message.clone());
+            return (Err(Terminate::Error(error)), steps);
+        }
+    };
+
+    for (span, statement) in compiled {
+        let resolved = statement.resolve(&mut Context::new(target, &mut state, timezone));
+        let value = match &resolved {
+            Ok(value) | Err(ExpressionError::Return { value, .. }) => Some(value.clone()),
+            Err(_) => None,
+        };
+        steps.push(model::TraceStep {
+            span: *span,
+            start: line_index.position(span.0),
+            end: line_index.position(span.1),
+            value,
+            assignments: trace::assignments(statement, &target.value),
+        });
+        match resolved {
+            Ok(value) => result = value,
+            Err(ExpressionError::Return { value, .. }) => return (Ok(value), steps),
+            Err(
+                err @ (ExpressionError::Abort { .. }
+                | ExpressionError::Fallible { .. }
+                | ExpressionError::Missing { .. }),
+            ) => return (Err(Terminate::Abort(err)), steps),
+            Err(err @ ExpressionError::Error { .. }) => return (Err(Terminate::Error(err)), steps),
+        }
+    }
+
+    (Ok(result), steps)
+}
+
+pub fn format_diagnostic(
+    diagnostic: &model::CompilationDiagnostic,
+    options: &model::FormatDiagnosticOptions,
+) -> Result<String, String> {
+    match &options.source {
+        Some(source) => render::render(diagnostic, source, options),
+        None => Ok(diagnostic.summary()),
+    }
+}
+
+/// Follows `sandbox::FORBIDDEN_FUNCTION_CODE`, out of the 100 to 999 range of VRL codes
+pub const NON_BOOLEAN_CONDITION_CODE: usize = 1001;
+
+/// A compiled program and its runtime, to execute it on many events without compiling it again
+pub struct Program {
+    program: model::CompiledProgram,
+    /// the sandbox it was compiled with, which decides the default timezone and the clock
+    sandbox: model::SandboxOptions,
+    statements: TracedStatements,
+    runtime: Runtime,
+    warnings: Vec<model::CompilationDiagnostic>,
+}
+
+impl Program {
+    pub fn new(
+        program: String,
+        options: &model::CompilationOptions,
+    ) -> Result<Self, model::ErrorCompilationOutput> {
+        let source = model::CompilationInput {
+            program,
+            state: TypeState::default(),
+            sandbox: options.sandbox.clone(),
+        };
+        let output = compile(&source)?;
+        Ok(Program {
+            program: output.program,
+            sandbox: options.sandbox.clone(),
+            statements: TracedStatements::new(source),
+            runtime: Runtime::default(),
+            warnings: output.warnings,
+        })
+    }
+
+    pub fn warnings(&self) -> &[model::CompilationDiagnostic] {
+        &self.warnings
+    }
+
+    pub fn execute(
+        &mut self,
+        event: model::ExecutionEvent,
+        options: &model::ExecutionOptions,
+    ) -> model::ExecutionOutput {
+        let (event, metadata) =
+            decode_input(event, options).map_err(model::ErrorExecutionOutput::InvalidInput)?;
+        self.execute_decoded(event, metadata, options)
+    }
+
+    fn execute_decoded(
+        &mut self,
+        event: Value,
+        metadata: Option<Value>,
+        options: &model::ExecutionOptions,
+    ) -> model::ExecutionOutput {
+        let timezone = resolve_timezone(options, &self.sandbox)?;
+        let clock = resolve_clock(options, &self.sandbox)?;
+        run(
+            &self.program,
+            &self.statements,
+            &mut self.runtime,
+            (event, metadata),
+            options,
+            &timezone,
+            clock,
+        )
+    }
+}
+
+/**
+ * Compiles named conditions once to tell which of them match each event, like the
+ * `filter` and `route` transforms of Vector. Conditions run on copies of the event.
+ */
+pub struct Router {
+    routes: Vec<(String, Program)>,
+}
+
+impl Router {
+    pub fn new(
+        routes: &model::Routes,
+        options: &model::CompilationOptions,
+    ) -> Result<Self, model::ErrorRouterOutput> {
+        let mut compiled = vec![];
+        let mut failed = vec![];
+        for (name, condition) in routes.iter() {
+            match Program::new(condition.clone(), options) {
+                Ok(program) => {
+                    let result_kind = program.program.final_type_info().result.kind().clone();
+                    if result_kind.is_boolean() {
+                        compiled.push((name.clone(), program));
+                    } else {
+                        failed.push(model::RouteCompilationError {
+                            route: name.clone(),
+                            errors: vec![non_boolean_condition(condition, &result_kind)],
+                        });
+                    }
+                }
+                Err(err) => failed.push(model::RouteCompilationError {
+                    route: name.clone(),
+                    errors: err.errors,
+                }),
+            }
+        }
+
+        if !failed.is_empty() {
+            return Err(model::ErrorRouterOutput { routes: failed });
+        }
+        Ok(Router { routes: compiled })
+    }
+
+    pub fn route(
+        &mut self,
+        event: &model::ExecutionEvent,
+        options: &model::ExecutionOptions,
+    ) -> Result<model::RouteOutput, model::ErrorExecutionOutput> {
+        // decoded once, so that an invalid event fails before any condition runs
+        let (event, metadata) = decode_input(event.clone(), options)
+            .map_err(model::ErrorExecutionOutput::InvalidInput)?;
+        let mut matched = vec![];
+        let mut terminations = BTreeMap::new();
+        for (name, program) in self.routes.iter_mut() {
+            match program.execute_decoded(event.clone(), metadata.clone(), options) {
+                Ok(output) => {
+                    if output.result == Value::Boolean(true) {
+                        matched.push(name.clone());
+                    }
+                }
+                Err(model::ErrorExecutionOutput::Termination(termination)) => {
+                    terminations.insert(name.clone(), *termination);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(model::RouteOutput {
+            matched,
+            terminations,
+        })
+    }
+}
+
+fn non_boolean_condition(condition: &str, result_kind: &Kind) -> model::CompilationDiagnostic {
+    let line_index = position::LineIndex::new(condition);
+    let trimmed_start = condition.len() - condition.trim_start().len();
+    let span = (trimmed_start, condition.trim_end().len().max(trimmed_start));
+    model::CompilationDiagnostic {
+        message: format!("condition must resolve to a boolean, not {}", result_kind),
+        code: NON_BOOLEAN_CONDITION_CODE,
+        severity: "error".to_owned(),
+        labels: vec![model::CompilationDiagnosticLabel {
+            message: format!("this resolves to {}", result_kind),
+            primary: true,
+            span,
+            start: None,
+            end: None,
+        }],
+        notes: vec![],
+        start: None,
+        end: None,
+    }
+    .locate(&line_index)
+}
+
+/**
+ * Runs a program on newline-delimited JSON pushed in chunks of bytes, keeping only the
+ * incomplete last line between chunks. Empty lines are skipped but still counted.
+ */
+pub struct Transformer {
+    program: Program,
+    options: model::ExecutionOptions,
+    buffer: Vec<u8>,
+    line: usize,
+}
+
+impl Transformer {
+    pub fn new(
+        program: String,
+        options: model::ExecutionOptions,
+    ) -> Result<Self, model::ErrorExecutionOutput> {
+        // invalid options would otherwise fail every line
+        resolve_timezone(&options, &options.sandbox)?;
+        resolve_clock(&options, &options.sandbox)?;
+        let compilation_options = model::CompilationOptions {
+            sandbox: options.sandbox.clone(),
+        };
+        let program = Program::new(program, &compilation_options)
+            .map_err(model::ErrorExecutionOutput::CompilationError)?;
+        Ok(Transformer {
+            program,
+            options,
+            buffer: vec![],
+            line: 0,
+        })
+    }
+
+    /// Transforms the complete lines of the chunk
+    pub fn push(&mut self, chunk: &[u8]) -> model::TransformOutput {
+        let mut output = model::TransformOutput::default();
+        let mut rest = chunk;
+        while let Some(end) = rest.iter().position(|byte| *byte == b'\n') {
+            let line = if self.buffer.is_empty() {
+                self.transform_line(&rest[..end])
+            } else {
+                self.buffer.extend_from_slice(&rest[..end]);
+                let buffered = std::mem::take(&mut self.buffer);
+                self.transform_line(&buffered)
+            };
+            output.add(self.line, line);
+            rest = &rest[end + 1..];
+        }
+        self.buffer.extend_from_slice(rest);
+        output
+    }
+
+    /// Transforms the last line when the input does not end with a newline
+    pub fn finish(&mut self) -> model::TransformOutput {
+        let mut output = model::TransformOutput::default();
+        if !self.buffer.is_empty() {
+            let buffered = std::mem::take(&mut self.buffer);
+            let line = self.transform_line(&buffered);
+            output.add(self.line, line);
+        }
+        output
+    }
+
+    fn transform_line(
+        &mut self,
+        line: &[u8],
+    ) -> Option<Result<String, model::ErrorExecutionOutput>> {
+        self.line += 1;
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if line.iter().all(u8::is_ascii_whitespace) {
+            return None;
+        }
+
+        let event: model::ExecutionEvent = match serde_json::from_slice(line) {
+            Ok(event) => event,
+            Err(err) => {
+                let message = format!("invalid JSON: {}", err);
+                return Some(Err(model::ErrorExecutionOutput::InvalidInput(message)));
+            }
+        };
+        let transformed = self
+            .program
+            .execute(event, &self.options)
+            .and_then(|output| {
+                serde_json::to_string(&output.event).map_err(|err| {
+                    let message = format!("unable to serialize the event: {}", err);
+                    model::ErrorExecutionOutput::Serialization(message)
+                })
+            });
+        Some(transformed)
+    }
+}
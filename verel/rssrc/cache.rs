@@ -0,0 +1,135 @@
+use crate::model;
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+pub const DEFAULT_CAPACITY: usize = 64;
+
+/// The source and serialized compile options, compared on lookup so hash collisions never mix programs
+#[derive(PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    program: String,
+    options: String,
+}
+
+impl CacheKey {
+    pub fn new(program: &str, options: &impl Serialize) -> Self {
+        CacheKey {
+            program: program.to_owned(),
+            // the options are plain data, serializing them cannot fail
+            options: serde_json::to_string(options).unwrap_or_default(),
+        }
+    }
+
+    fn hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        Hash::hash(self, &mut hasher);
+        hasher.finish()
+    }
+}
+
+struct Entry {
+    hash: u64,
+    key: CacheKey,
+    output: Rc<model::CompilationOutput>,
+}
+
+/// Entries from least to most recently used; capacities are small, so a linear scan is enough
+struct ProgramCache {
+    capacity: usize,
+    entries: Vec<Entry>,
+    hits: u64,
+    misses: u64,
+}
+
+thread_local! {
+    static CACHE: RefCell<ProgramCache> = const {
+        RefCell::new(ProgramCache {
+            capacity: DEFAULT_CAPACITY,
+            entries: Vec::new(),
+            hits: 0,
+            misses: 0,
+        })
+    };
+}
+
+/**
+ * Returns the cached compilation output for the key, or compiles and caches it, evicting
+ * the least recently used entry when the cache is full. Failed compilations are cached too.
+ */
+pub fn get_or_compile(
+    key: CacheKey,
+    compile: impl FnOnce() -> model::CompilationOutput,
+) -> Rc<model::CompilationOutput> {
+    let hash = key.hash();
+    let cached = CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        let position = cache
+            .entries
+            .iter()
+            .position(|entry| entry.hash == hash && entry.key == key);
+        match position {
+            Some(position) => {
+                cache.hits += 1;
+                let entry = cache.entries.remove(position);
+                let output = entry.output.clone();
+                cache.entries.push(entry);
+                Some(output)
+            }
+            None => {
+                cache.misses += 1;
+                None
+            }
+        }
+    });
+    if let Some(output) = cached {
+        return output;
+    }
+
+    // compile without holding the cache borrowed
+    let output = Rc::new(compile());
+    CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        if cache.capacity == 0 {
+            return;
+        }
+        while cache.entries.len() >= cache.capacity {
+            cache.entries.remove(0);
+        }
+        cache.entries.push(Entry {
+            hash,
+            key,
+            output: output.clone(),
+        });
+    });
+    output
+}
+
+/// Sets the number of programs kept, evicting the least recently used ones; 0 disables the cache
+pub fn configure(options: &model::CacheOptions) {
+    CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        cache.capacity = options.capacity;
+        let excess = cache.entries.len().saturating_sub(options.capacity);
+        cache.entries.drain(..excess);
+    });
+}
+
+/// Drops every cached program, keeping the statistics
+pub fn clear() {
+    CACHE.with(|cache| cache.borrow_mut().entries.clear());
+}
+
+pub fn stats() -> model::CacheStats {
+    CACHE.with(|cache| {
+        let cache = cache.borrow();
+        model::CacheStats {
+            capacity: cache.capacity,
+            size: cache.entries.len(),
+            hits: cache.hits,
+            misses: cache.misses,
+        }
+    })
+}
@@ -0,0 +1,144 @@
+//! The tagged encoding keeps the VRL types that JSON cannot represent:
+//!
+//! - `{ "$type": "integer", "value": "9007199254740993" }` for integers beyond 2^53
+//! - `{ "$type": "float", "value": 1 }` for floats without a fractional part
+//! - `{ "$type": "timestamp", "value": "2024-01-01T00:00:00.123456789Z" }`
+//! - `{ "$type": "bytes", "value": [255, 0] }` for bytes that are not valid UTF-8
+//! - `{ "$type": "regex", "value": "^foo" }`
+//! - `{ "$type": "object", "value": { ... } }` for objects that have a "$type" field of their own
+//!
+//! Every other value is plain JSON. Outputs are returned tagged as they are: JavaScript
+//! callers turn them into bigint, Date, etc. with `decodeTaggedValue`.
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::collections::BTreeMap;
+use vrl::value::{ObjectMap, Value};
+
+const TYPE_FIELD: &str = "$type";
+const VALUE_FIELD: &str = "value";
+const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+fn tagged(value_type: &str, value: Value) -> Value {
+    let mut fields = ObjectMap::new();
+    fields.insert(TYPE_FIELD.into(), Value::from(value_type));
+    fields.insert(VALUE_FIELD.into(), value);
+    Value::Object(fields)
+}
+
+pub fn encode(value: Value) -> Value {
+    match value {
+        Value::Integer(integer) if integer.unsigned_abs() > MAX_SAFE_INTEGER => {
+            tagged("integer", Value::from(integer.to_string()))
+        }
+        Value::Float(float) if float.fract() == 0.0 => tagged("float", Value::Float(float)),
+        Value::Bytes(bytes) if std::str::from_utf8(&bytes).is_err() => tagged(
+            "bytes",
+            Value::Array(bytes.iter().map(|byte| Value::from(*byte as i64)).collect()),
+        ),
+        Value::Timestamp(timestamp) => tagged(
+            "timestamp",
+            Value::from(timestamp.to_rfc3339_opts(SecondsFormat::AutoSi, true)),
+        ),
+        Value::Regex(regex) => tagged("regex", Value::from(regex.as_str())),
+        Value::Object(fields) => {
+            let escaped = fields.contains_key(TYPE_FIELD);
+            let fields: ObjectMap = fields
+                .into_iter()
+                .map(|(key, value)| (key, encode(value)))
+                .collect();
+            if escaped {
+                tagged("object", Value::Object(fields))
+            } else {
+                Value::Object(fields)
+            }
+        }
+        Value::Array(items) => Value::Array(items.into_iter().map(encode).collect()),
+        value => value,
+    }
+}
+
+pub fn decode(value: Value) -> Result<Value, String> {
+    decode_at(value, ".")
+}
+
+fn decode_at(value: Value, path: &str) -> Result<Value, String> {
+    match value {
+        Value::Object(mut fields) if fields.contains_key(TYPE_FIELD) => {
+            let value_type = match fields.remove(TYPE_FIELD) {
+                Some(Value::Bytes(value_type)) => String::from_utf8_lossy(&value_type).into_owned(),
+                _ => return Err(format!("{}: \"$type\" must be a string", path)),
+            };
+            let value = fields
+                .remove(VALUE_FIELD)
+                .ok_or_else(|| format!("{}: tagged {} has no \"value\"", path, value_type))?;
+            decode_tagged(&value_type, value).map_err(|err| format!("{}: {}", path, err))
+        }
+        Value::Object(fields) => Ok(Value::Object(decode_fields(fields, path)?)),
+        Value::Array(items) => items
+            .into_iter()
+            .enumerate()
+            .map(|(index, item)| decode_at(item, &child_path(path, &format!("[{}]", index))))
+            .collect::<Result<Vec<Value>, String>>()
+            .map(Value::Array),
+        value => Ok(value),
+    }
+}
+
+fn decode_fields(fields: ObjectMap, path: &str) -> Result<ObjectMap, String> {
+    fields
+        .into_iter()
+        .map(|(key, value)| {
+            let field_path = child_path(path, &format!(".{}", key));
+            decode_at(value, &field_path).map(|value| (key, value))
+        })
+        .collect::<Result<BTreeMap<_, _>, String>>()
+}
+
+fn decode_tagged(value_type: &str, value: Value) -> Result<Value, String> {
+    match (value_type, value) {
+        ("integer", Value::Integer(integer)) => Ok(Value::Integer(integer)),
+        ("integer", Value::Bytes(digits)) => String::from_utf8_lossy(&digits)
+            .parse::<i64>()
+            .map(Value::Integer)
+            .map_err(|err| format!("invalid integer: {}", err)),
+        ("float", Value::Integer(integer)) => Ok(Value::from_f64_or_zero(integer as f64)),
+        ("float", Value::Float(float)) => Ok(Value::Float(float)),
+        ("timestamp", Value::Bytes(timestamp)) => {
+            DateTime::parse_from_rfc3339(&String::from_utf8_lossy(&timestamp))
+                .map(|timestamp| Value::Timestamp(timestamp.with_timezone(&Utc)))
+                .map_err(|err| format!("invalid timestamp: {}", err))
+        }
+        ("bytes", Value::Array(items)) => items
+            .into_iter()
+            .map(|item| match item {
+                Value::Integer(byte) => u8::try_from(byte).map_err(|_| byte.to_string()),
+                other => Err(other.to_string()),
+            })
+            .collect::<Result<Vec<u8>, String>>()
+            .map(|bytes| Value::Bytes(bytes.into()))
+            .map_err(|item| format!("invalid byte {}", item)),
+        ("regex", Value::Bytes(pattern)) => regex::Regex::new(&String::from_utf8_lossy(&pattern))
+            .map(Value::from)
+            .map_err(|err| format!("invalid regex: {}", err)),
+        ("object", Value::Object(fields)) => {
+            // the escaped fields may include "$type", which is a plain field here
+            fields
+                .into_iter()
+                .map(|(key, value)| decode(value).map(|value| (key, value)))
+                .collect::<Result<ObjectMap, String>>()
+                .map(Value::Object)
+        }
+        (value_type, value) => Err(format!(
+            "invalid value {} for tagged type \"{}\"",
+            value, value_type
+        )),
+    }
+}
+
+fn child_path(path: &str, segment: &str) -> String {
+    if path == "." {
+        format!(".{}", segment.trim_start_matches('.'))
+    } else {
+        format!("{}{}", path, segment)
+    }
+}
@@ -0,0 +1,103 @@
+use chrono::{DateTime, Utc};
+use std::cell::Cell;
+use vrl::prelude::*;
+
+/**
+ * Functions whose result depends on something else than their arguments and the execution
+ * options (randomness, the host, the network or the current year, which `parse_syslog`, its
+ * alias `parse_linux_authorization` and `parse_klog` give the dates missing one);
+ * deterministic programs cannot call them.
+ * `now()` is not one of them, it returns the `clock` execution option instead.
+ */
+pub const NON_DETERMINISTIC_FUNCTIONS: &[&str] = &[
+    "uuid_v4",
+    "uuid_v7",
+    "random_bool",
+    "random_bytes",
+    "random_float",
+    "random_int",
+    "get_env_var",
+    "get_hostname",
+    "dns_lookup",
+    "reverse_dns",
+    "parse_syslog",
+    "parse_linux_authorization",
+    "parse_klog",
+];
+
+thread_local! {
+    static CLOCK: Cell<Option<DateTime<Utc>>> = const { Cell::new(None) };
+}
+
+/// Sets the time `now()` returns in deterministic programs, until the next execution
+pub fn set_clock(clock: Option<DateTime<Utc>>) {
+    CLOCK.with(|current| current.set(clock));
+}
+
+/**
+ * Replaces `now()` by a function returning the clock set for the execution. Executions of
+ * deterministic programs are rejected without a clock, so it stays infallible like `now()`.
+ */
+pub fn fix_clock(functions: Vec<Box<dyn Function>>) -> Vec<Box<dyn Function>> {
+    functions
+        .into_iter()
+        .map(|function| {
+            if function.identifier() == "now" {
+                Box::new(FixedClockFunction { inner: function }) as Box<dyn Function>
+            } else {
+                function
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug)]
+struct FixedClockFunction {
+    inner: Box<dyn Function>,
+}
+
+impl Function for FixedClockFunction {
+    fn identifier(&self) -> &'static str {
+        self.inner.identifier()
+    }
+
+    fn summary(&self) -> &'static str {
+        self.inner.summary()
+    }
+
+    fn usage(&self) -> &'static str {
+        self.inner.usage()
+    }
+
+    fn examples(&self) -> &'static [Example] {
+        self.inner.examples()
+    }
+
+    fn parameters(&self) -> &'static [Parameter] {
+        self.inner.parameters()
+    }
+
+    fn compile(
+        &self,
+        _state: &TypeState,
+        _ctx: &mut FunctionCompileContext,
+        _arguments: ArgumentList,
+    ) -> Compiled {
+        Ok(FixedClockFn.as_expr())
+    }
+}
+
+#[derive(Clone, Debug)]
+struct FixedClockFn;
+
+impl FunctionExpression for FixedClockFn {
+    fn resolve(&self, _ctx: &mut Context) -> Resolved {
+        // never unset here, executions of deterministic programs are rejected without a clock
+        let clock = CLOCK.with(Cell::get).unwrap_or_default();
+        Ok(Value::Timestamp(clock))
+    }
+
+    fn type_def(&self, _state: &TypeState) -> TypeDef {
+        TypeDef::timestamp().infallible()
+    }
+}
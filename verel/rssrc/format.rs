@@ -0,0 +1,357 @@
+//! Pretty-prints a VRL program in a canonical style: four spaces per nesting level,
+//! one statement per line, single spaces around binary operators and after commas,
+//! at most one blank line in a row. Comments are kept where they were written.
+//! Object literals written on several lines get one field per line.
+//!
+//! The program is expected to parse: the formatter works on tokens and does not
+//! try to make sense of invalid code.
+
+use crate::lexer::{self, Token, TokenKind};
+
+const INDENT: &str = "    ";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Group {
+    Block,
+    Object { multiline: bool },
+    Array,
+    Paren,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Role {
+    Plain,
+    /// `!` or `-` applied to the following operand
+    Unary,
+    /// `!` between a function name and its arguments
+    Fallible,
+    ClosureOpen,
+    ClosureClose,
+    BlockOpen,
+    BlockClose,
+    ObjectOpen,
+    ObjectClose,
+}
+
+struct Printer {
+    lines: Vec<String>,
+    line: String,
+    blank_line_pending: bool,
+    continuation: bool,
+    /// indentation level of the current line
+    indent: usize,
+}
+
+impl Printer {
+    fn write(&mut self, text: &str, depth: usize, space: bool) {
+        if self.line.is_empty() {
+            let after_opening = self
+                .lines
+                .last()
+                .is_none_or(|line| line.is_empty() || line.ends_with(['{', '[', '(']));
+            if self.blank_line_pending && !after_opening {
+                self.lines.push(String::new());
+            }
+            self.blank_line_pending = false;
+            self.indent = if self.continuation { depth + 1 } else { depth };
+            self.line.push_str(&INDENT.repeat(self.indent));
+        } else if space {
+            self.line.push(' ');
+        }
+        self.line.push_str(text);
+    }
+
+    fn end_line(&mut self) {
+        if !self.line.is_empty() {
+            let line = std::mem::take(&mut self.line);
+            self.lines.push(line.trim_end().to_owned());
+        }
+    }
+
+    fn finish(mut self) -> String {
+        self.end_line();
+        if self.lines.is_empty() {
+            return String::new();
+        }
+        let mut output = self.lines.join("\n");
+        output.push('\n');
+        output
+    }
+}
+
+pub fn format(source: &str) -> String {
+    let tokens = lexer::tokenize(source);
+
+    let mut printer = Printer {
+        lines: vec![],
+        line: String::new(),
+        blank_line_pending: false,
+        continuation: false,
+        indent: 0,
+    };
+    // open groups with the indentation of the line they were opened on, so that
+    // several openers on one line like `join!([` only indent their content once
+    let mut groups: Vec<(Group, usize)> = vec![];
+    // depths at which an `if` is waiting for the `{` of its block
+    let mut if_conditions: Vec<usize> = vec![];
+    let mut in_closure_parameters = false;
+    let mut statement_start = true;
+    let mut newlines = 0;
+    let mut previous: Option<(Token, Role)> = None;
+
+    for (index, token) in tokens.iter().enumerate() {
+        let in_block = groups
+            .last()
+            .is_none_or(|(group, _)| *group == Group::Block);
+        let innermost_object = match groups.last() {
+            Some((Group::Object { multiline }, _)) => Some(*multiline),
+            _ => None,
+        };
+        let depth = groups.last().map_or(0, |(_, indent)| indent + 1);
+
+        match token.kind {
+            TokenKind::Whitespace => continue,
+            // the line breaks of object literals are decided below
+            TokenKind::Newline if innermost_object.is_some() => continue,
+            TokenKind::Newline => {
+                newlines += 1;
+                if in_block {
+                    let ends_with_operator = previous.is_some_and(|(previous, role)| {
+                        previous.kind == TokenKind::Operator && role == Role::Plain
+                    });
+                    if !statement_start && ends_with_operator {
+                        printer.continuation = true;
+                    } else {
+                        printer.continuation = false;
+                        statement_start = true;
+                    }
+                }
+                printer.end_line();
+                continue;
+            }
+            TokenKind::Comment => {
+                if newlines > 1 {
+                    printer.blank_line_pending = true;
+                }
+                newlines = 0;
+                printer.write(token.text.trim_end(), depth, true);
+                printer.end_line();
+                continue;
+            }
+            _ => {}
+        }
+
+        if token.text == "," && innermost_object == Some(true) {
+            printer.write(token.text, depth, false);
+            printer.end_line();
+            previous = Some((*token, Role::Plain));
+            continue;
+        }
+
+        if token.text == ";" {
+            printer.end_line();
+            printer.continuation = false;
+            statement_start = true;
+            continue;
+        }
+
+        let role = role(
+            &tokens,
+            index,
+            previous,
+            &groups,
+            &if_conditions,
+            in_closure_parameters,
+            statement_start,
+        );
+
+        // `}` and `else` stay on the same line, and so do the braces of an empty block
+        let joined = match previous {
+            Some((_, Role::BlockClose)) => token.text == "else",
+            Some((_, Role::BlockOpen)) => role == Role::BlockClose,
+            _ => false,
+        };
+        if joined {
+            if printer.line.is_empty() {
+                printer.line = printer.lines.pop().unwrap_or_default();
+            }
+            newlines = 0;
+        }
+
+        let depth = match role {
+            Role::BlockClose | Role::ObjectClose => {
+                if (role == Role::BlockClose && !joined) || innermost_object == Some(true) {
+                    printer.end_line();
+                    printer.continuation = false;
+                }
+                groups.pop().map_or(0, |(_, indent)| indent)
+            }
+            _ if matches!(token.text, ")" | "]") => groups.pop().map_or(0, |(_, indent)| indent),
+            _ => depth,
+        };
+
+        if newlines > 1 && !matches!(role, Role::BlockClose) {
+            printer.blank_line_pending = true;
+        }
+        newlines = 0;
+
+        let space = previous.is_some_and(|previous| needs_space(previous, (*token, role)));
+        printer.write(token.text, depth, space);
+
+        match role {
+            Role::BlockOpen => {
+                groups.push((Group::Block, printer.indent));
+                if if_conditions.last() == Some(&(groups.len() - 1)) {
+                    if_conditions.pop();
+                }
+                printer.end_line();
+                printer.continuation = false;
+                statement_start = true;
+            }
+            Role::BlockClose => {
+                statement_start = true;
+            }
+            Role::ObjectOpen => {
+                let multiline = spans_lines(&tokens[index + 1..]);
+                groups.push((Group::Object { multiline }, printer.indent));
+                if multiline {
+                    printer.end_line();
+                }
+                statement_start = false;
+            }
+            Role::ClosureOpen => {
+                in_closure_parameters = true;
+                statement_start = false;
+            }
+            Role::ClosureClose => {
+                in_closure_parameters = false;
+                statement_start = false;
+            }
+            _ => {
+                match token.text {
+                    "(" => groups.push((Group::Paren, printer.indent)),
+                    "[" => groups.push((Group::Array, printer.indent)),
+                    "if" if token.kind == TokenKind::Ident => if_conditions.push(groups.len()),
+                    _ => {}
+                }
+                statement_start = false;
+            }
+        }
+        previous = Some((*token, role));
+    }
+
+    printer.finish()
+}
+
+fn role(
+    tokens: &[Token],
+    index: usize,
+    previous: Option<(Token, Role)>,
+    groups: &[(Group, usize)],
+    if_conditions: &[usize],
+    in_closure_parameters: bool,
+    statement_start: bool,
+) -> Role {
+    let token = tokens[index];
+    let next = tokens[index + 1..].iter().find(|token| !token.is_trivia());
+    let previous_ends_operand = previous.is_some_and(|(previous, role)| {
+        previous.ends_operand() || matches!(role, Role::BlockClose | Role::ObjectClose)
+    });
+
+    match token.text {
+        "{" => {
+            let after_else = previous.is_some_and(|(previous, _)| previous.text == "else");
+            let after_closure = previous.is_some_and(|(_, role)| role == Role::ClosureClose);
+            let after_condition = if_conditions.last() == Some(&groups.len());
+            if after_else
+                || after_closure
+                || after_condition
+                || statement_start && !looks_like_object(&tokens[index + 1..])
+            {
+                Role::BlockOpen
+            } else {
+                Role::ObjectOpen
+            }
+        }
+        "}" => match groups.last() {
+            Some((Group::Object { .. }, _)) => Role::ObjectClose,
+            _ => Role::BlockClose,
+        },
+        "|" if previous.is_some_and(|(previous, _)| previous.text == "->") => Role::ClosureOpen,
+        "|" if in_closure_parameters => Role::ClosureClose,
+        "!" if token.kind == TokenKind::Operator
+            && previous.is_some_and(|(previous, _)| {
+                previous.kind == TokenKind::Ident && previous.ends_operand()
+            })
+            && next.map(|next| next.text) == Some("(") =>
+        {
+            Role::Fallible
+        }
+        "!" | "-" if token.kind == TokenKind::Operator && !previous_ends_operand => Role::Unary,
+        _ => Role::Plain,
+    }
+}
+
+fn needs_space(previous: (Token, Role), next: (Token, Role)) -> bool {
+    let (previous, previous_role) = previous;
+    let (next, next_role) = next;
+
+    if matches!(
+        previous_role,
+        Role::Unary | Role::Fallible | Role::ClosureOpen
+    ) {
+        return false;
+    }
+    if matches!(next_role, Role::Fallible | Role::ClosureClose) {
+        return false;
+    }
+    if previous_role == Role::BlockOpen && next_role == Role::BlockClose {
+        return false;
+    }
+    if previous_role == Role::ObjectOpen {
+        return next_role != Role::ObjectClose;
+    }
+    if matches!(previous.text, "(" | "[") {
+        return false;
+    }
+    if matches!(next.text, ")" | "]" | "," | ":") || next.kind == TokenKind::FieldAccess {
+        return false;
+    }
+    if next.text == "(" && previous.kind == TokenKind::Ident && previous.ends_operand() {
+        return false;
+    }
+    if next.text == "[" && (previous.ends_operand() || previous_role == Role::ObjectClose) {
+        return false;
+    }
+    true
+}
+
+/// A statement starting with `{` is a block, unless it is an object literal like `{ "a": 1 }` or `{}`
+fn looks_like_object(rest: &[Token]) -> bool {
+    let mut significant = rest.iter().filter(|token| !token.is_trivia());
+    match significant.next() {
+        Some(token) if token.text == "}" => true,
+        Some(token) if matches!(token.kind, TokenKind::String | TokenKind::RawString) => {
+            significant.next().map(|token| token.text) == Some(":")
+        }
+        _ => false,
+    }
+}
+
+/// True when the group opened right before `rest` is closed on another line
+fn spans_lines(rest: &[Token]) -> bool {
+    let mut depth = 0;
+    for token in rest {
+        match token.kind {
+            TokenKind::Newline => return true,
+            TokenKind::Comment => {}
+            _ => match token.text {
+                "{" | "[" | "(" => depth += 1,
+                "}" | "]" | ")" if depth == 0 => return false,
+                "}" | "]" | ")" => depth -= 1,
+                _ => {}
+            },
+        }
+    }
+    false
+}
@@ -0,0 +1,326 @@
+use crate::cache;
+use crate::model;
+use gloo_utils::format::JsValueSerdeExt;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use vrl::prelude::*;
+use vrl::value::kind::Collection;
+use vrl::value::Kind;
+use wasm_bindgen::JsValue;
+
+/**
+ * The registered functions and their JS callbacks. Callbacks never leave this thread: compiled
+ * programs only hold their id, so host functions stay Send + Sync as VRL requires.
+ */
+struct Registry {
+    functions: BTreeMap<String, HostFunction>,
+    callbacks: BTreeMap<u64, js_sys::Function>,
+    next_callback_id: u64,
+    /// VRL requires identifiers and parameters to be 'static, so they are leaked once and
+    /// reused when registering again, instead of leaking on every registration
+    identifiers: BTreeSet<&'static str>,
+    parameters: Vec<&'static [Parameter]>,
+}
+
+thread_local! {
+    static REGISTRY: RefCell<Registry> = const {
+        RefCell::new(Registry {
+            functions: BTreeMap::new(),
+            callbacks: BTreeMap::new(),
+            next_callback_id: 0,
+            identifiers: BTreeSet::new(),
+            parameters: Vec::new(),
+        })
+    };
+}
+
+impl Registry {
+    fn identifier(&mut self, name: &str) -> &'static str {
+        if let Some(identifier) = self.identifiers.get(name) {
+            return identifier;
+        }
+        let identifier: &'static str = Box::leak(name.to_owned().into_boxed_str());
+        self.identifiers.insert(identifier);
+        identifier
+    }
+
+    fn parameters(&mut self, parameters: Vec<Parameter>) -> &'static [Parameter] {
+        if let Some(interned) = self
+            .parameters
+            .iter()
+            .find(|interned| **interned == parameters.as_slice())
+        {
+            return interned;
+        }
+        let interned: &'static [Parameter] = Box::leak(parameters.into_boxed_slice());
+        self.parameters.push(interned);
+        interned
+    }
+
+    fn remove(&mut self, name: &str) -> bool {
+        match self.functions.remove(name) {
+            Some(function) => {
+                self.callbacks.remove(&function.callback_id);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/**
+ * Registers a JS callback as a VRL function available to every subsequent compilation.
+ *
+ * Host functions are always fallible since the callback may throw or return a value
+ * that does not match the declared return kind.
+ */
+pub fn register(
+    definition: model::HostFunctionDefinition,
+    callback: js_sys::Function,
+) -> Result<(), String> {
+    let builtin_conflict = crate::api::builtin_functions()
+        .iter()
+        .any(|function| function.identifier() == definition.name);
+    if builtin_conflict {
+        return Err(format!(
+            "function \"{}\" conflicts with a builtin function",
+            definition.name
+        ));
+    }
+
+    let mut parameters = vec![];
+    for parameter in definition.parameters.iter() {
+        parameters.push((
+            parameter.keyword.as_str(),
+            kind_mask(&parameter.kind).map_err(|err| {
+                format!(
+                    "function \"{}\", parameter \"{}\": {}",
+                    definition.name, parameter.keyword, err
+                )
+            })?,
+            parameter.required,
+        ));
+    }
+
+    let return_kind = to_kind(&definition.return_kind)
+        .map_err(|err| format!("function \"{}\", return kind: {}", definition.name, err))?;
+
+    REGISTRY.with(|registry| {
+        let mut registry = registry.borrow_mut();
+        let parameters = parameters
+            .into_iter()
+            .map(|(keyword, kind, required)| Parameter {
+                keyword: registry.identifier(keyword),
+                kind,
+                required,
+            })
+            .collect();
+
+        let callback_id = registry.next_callback_id;
+        registry.next_callback_id += 1;
+        registry.callbacks.insert(callback_id, callback);
+
+        let function = HostFunction {
+            identifier: registry.identifier(&definition.name),
+            parameters: registry.parameters(parameters),
+            return_kind,
+            callback_id,
+        };
+        registry.remove(&definition.name);
+        registry.functions.insert(definition.name, function);
+    });
+    // cached programs were compiled against the previous set of functions
+    cache::clear();
+    Ok(())
+}
+
+pub fn unregister(name: &str) -> bool {
+    let removed = REGISTRY.with(|registry| registry.borrow_mut().remove(name));
+    if removed {
+        cache::clear();
+    }
+    removed
+}
+
+pub fn functions() -> Vec<Box<dyn Function>> {
+    REGISTRY.with(|registry| {
+        registry
+            .borrow()
+            .functions
+            .values()
+            .map(|function| Box::new(function.clone()) as Box<dyn Function>)
+            .collect()
+    })
+}
+
+fn kind_names(kind: &model::HostKind) -> Vec<&str> {
+    match kind {
+        model::HostKind::Single(name) => vec![name.as_str()],
+        model::HostKind::Many(names) => names.iter().map(|name| name.as_str()).collect(),
+    }
+}
+
+fn kind_mask(kind: &model::HostKind) -> Result<u16, String> {
+    let mut mask = 0;
+    for name in kind_names(kind) {
+        mask |= match name {
+            "any" => kind::ANY,
+            "string" => kind::BYTES,
+            "integer" => kind::INTEGER,
+            "float" => kind::FLOAT,
+            "boolean" => kind::BOOLEAN,
+            "object" => kind::OBJECT,
+            "array" => kind::ARRAY,
+            "timestamp" => kind::TIMESTAMP,
+            "regex" => kind::REGEX,
+            "null" => kind::NULL,
+            other => return Err(format!("unsupported kind \"{}\"", other)),
+        };
+    }
+    Ok(mask)
+}
+
+fn to_kind(kind: &model::HostKind) -> Result<Kind, String> {
+    let mut result = Kind::never();
+    for name in kind_names(kind) {
+        result = match name {
+            "any" => return Ok(Kind::any()),
+            "string" => result.or_bytes(),
+            "integer" => result.or_integer(),
+            "float" => result.or_float(),
+            "boolean" => result.or_boolean(),
+            "object" => result.or_object(Collection::any()),
+            "array" => result.or_array(Collection::any()),
+            "timestamp" => result.or_timestamp(),
+            "regex" => result.or_regex(),
+            "null" => result.or_null(),
+            other => return Err(format!("unsupported kind \"{}\"", other)),
+        };
+    }
+    Ok(result)
+}
+
+#[derive(Clone, Debug)]
+struct HostFunction {
+    identifier: &'static str,
+    parameters: &'static [Parameter],
+    return_kind: Kind,
+    callback_id: u64,
+}
+
+impl Function for HostFunction {
+    fn identifier(&self) -> &'static str {
+        self.identifier
+    }
+
+    fn usage(&self) -> &'static str {
+        "Host function registered from JavaScript."
+    }
+
+    fn examples(&self) -> &'static [Example] {
+        &[]
+    }
+
+    fn parameters(&self) -> &'static [Parameter] {
+        self.parameters
+    }
+
+    fn compile(
+        &self,
+        _state: &TypeState,
+        _ctx: &mut FunctionCompileContext,
+        arguments: ArgumentList,
+    ) -> Compiled {
+        let arguments = self
+            .parameters
+            .iter()
+            .map(|parameter| arguments.optional(parameter.keyword))
+            .collect();
+
+        Ok(HostFunctionFn {
+            identifier: self.identifier,
+            arguments,
+            return_kind: self.return_kind.clone(),
+            callback_id: self.callback_id,
+        }
+        .as_expr())
+    }
+}
+
+#[derive(Clone, Debug)]
+struct HostFunctionFn {
+    identifier: &'static str,
+    arguments: Vec<Option<Box<dyn Expression>>>,
+    return_kind: Kind,
+    callback_id: u64,
+}
+
+impl HostFunctionFn {
+    fn call(&self, args: &js_sys::Array) -> Result<JsValue, String> {
+        let callback = REGISTRY
+            .with(|registry| registry.borrow().callbacks.get(&self.callback_id).cloned())
+            .ok_or_else(|| format!("function \"{}\" is no longer registered", self.identifier))?;
+
+        callback.apply(&JsValue::NULL, args).map_err(|err| {
+            let message = err
+                .as_string()
+                .or_else(|| js_sys::Error::from(err).message().as_string())
+                .unwrap_or_else(|| "unknown error".to_owned());
+            format!("function \"{}\" failed: {}", self.identifier, message)
+        })
+    }
+}
+
+impl FunctionExpression for HostFunctionFn {
+    fn resolve(&self, ctx: &mut Context) -> Resolved {
+        let args = js_sys::Array::new();
+        for argument in self.arguments.iter() {
+            let value = match argument {
+                Some(expression) => expression.resolve(ctx)?,
+                None => Value::Null,
+            };
+            let js_value = JsValue::from_serde(&value).map_err(|err| {
+                format!(
+                    "function \"{}\": unable to pass argument to host: {}",
+                    self.identifier, err
+                )
+            })?;
+            args.push(&js_value);
+        }
+
+        let returned = self.call(&args)?;
+        let value = if returned.is_undefined() {
+            Value::Null
+        } else {
+            // `into_serde` would throw on cyclic objects and BigInt values
+            js_sys::JSON::stringify(&returned)
+                .ok()
+                .and_then(|json| json.as_string())
+                .ok_or_else(|| "value is not serializable".to_owned())
+                .and_then(|json| {
+                    serde_json::from_str::<Value>(&json).map_err(|err| err.to_string())
+                })
+                .map_err(|err| {
+                    format!(
+                        "function \"{}\": unable to read value returned by host: {}",
+                        self.identifier, err
+                    )
+                })?
+        };
+
+        if self.return_kind.is_superset(&value.kind()).is_err() {
+            return Err(format!(
+                "function \"{}\" returned {} but declares {}",
+                self.identifier,
+                value.kind_str(),
+                self.return_kind
+            )
+            .into());
+        }
+        Ok(value)
+    }
+
+    fn type_def(&self, _state: &TypeState) -> TypeDef {
+        TypeDef::from(self.return_kind.clone()).fallible()
+    }
+}
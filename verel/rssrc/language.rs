@@ -0,0 +1,329 @@
+use crate::lexer::{self, Token, TokenKind};
+use crate::model;
+use vrl::compiler::{compile_with_state, CompileConfig, Function, TypeState};
+use vrl::value::Kind;
+
+const KEYWORDS: &[&str] = &["if", "else", "abort", "return", "null", "true", "false"];
+const MAX_PATH_DEPTH: usize = 8;
+
+/**
+ * What the editor features know about the program environment: the functions that can be
+ * called and the kinds of the event, metadata and local variables, ideally after the program ran.
+ */
+pub struct LanguageContext {
+    pub functions: Vec<Box<dyn Function>>,
+    pub target_kind: Kind,
+    pub metadata_kind: Kind,
+    pub state: TypeState,
+}
+
+impl LanguageContext {
+    /// VRL keeps local variables private to its type state, so their kind is read by compiling them alone
+    fn variable_kind(&self, name: &str) -> Option<Kind> {
+        let compiled =
+            compile_with_state(name, &self.functions, &self.state, CompileConfig::default())
+                .ok()?;
+        Some(compiled.program.final_type_info().result.kind().clone())
+    }
+}
+
+pub fn complete(source: &str, offset: usize, context: &LanguageContext) -> model::CompletionOutput {
+    let tokens = lexer::tokenize(source);
+    let token = lexer::token_before(&tokens, offset).map(|index| tokens[index]);
+
+    // `None` when completing in between tokens, where anything can be inserted
+    let (span, prefix, token_kind) = match token {
+        Some(token)
+            if matches!(
+                token.kind,
+                TokenKind::Ident | TokenKind::EventPath | TokenKind::MetadataPath
+            ) =>
+        {
+            let prefix = source.get(token.span.0..offset).unwrap_or_default();
+            (token.span, prefix, Some(token.kind))
+        }
+        Some(token)
+            if matches!(
+                token.kind,
+                TokenKind::String
+                    | TokenKind::RawString
+                    | TokenKind::Comment
+                    | TokenKind::Number
+                    | TokenKind::FieldAccess
+            ) && offset > token.span.0 =>
+        {
+            return model::CompletionOutput {
+                span: (offset, offset),
+                items: vec![],
+            };
+        }
+        _ => ((offset, offset), "", None),
+    };
+
+    let mut items = vec![];
+
+    if matches!(token_kind, Some(TokenKind::EventPath) | None) {
+        for (path, kind) in known_paths(&context.target_kind, ".") {
+            if path.starts_with(prefix) {
+                items.push(model::CompletionItem {
+                    label: path,
+                    kind: model::CompletionItemKind::Path,
+                    detail: Some(kind.to_string()),
+                    documentation: None,
+                });
+            }
+        }
+    }
+
+    if matches!(token_kind, Some(TokenKind::MetadataPath) | None) {
+        for (path, kind) in known_paths(&context.metadata_kind, "%") {
+            if path.starts_with(prefix) {
+                items.push(model::CompletionItem {
+                    label: path,
+                    kind: model::CompletionItemKind::Path,
+                    detail: Some(kind.to_string()),
+                    documentation: None,
+                });
+            }
+        }
+    }
+
+    if matches!(token_kind, Some(TokenKind::Ident) | None) {
+        for variable in variables(&tokens, span.0) {
+            if variable.starts_with(prefix) && !items.iter().any(|item| item.label == variable) {
+                items.push(model::CompletionItem {
+                    detail: context
+                        .variable_kind(&variable)
+                        .map(|kind| kind.to_string()),
+                    label: variable,
+                    kind: model::CompletionItemKind::Variable,
+                    documentation: None,
+                });
+            }
+        }
+
+        for function in context.functions.iter() {
+            if function.identifier().starts_with(prefix) {
+                items.push(model::CompletionItem {
+                    label: function.identifier().to_owned(),
+                    kind: model::CompletionItemKind::Function,
+                    detail: Some(signature(function.as_ref())),
+                    documentation: documentation(function.as_ref()),
+                });
+            }
+        }
+
+        for keyword in KEYWORDS.iter() {
+            if keyword.starts_with(prefix) {
+                items.push(model::CompletionItem {
+                    label: keyword.to_string(),
+                    kind: model::CompletionItemKind::Keyword,
+                    detail: None,
+                    documentation: None,
+                });
+            }
+        }
+    }
+
+    model::CompletionOutput { span, items }
+}
+
+pub fn hover(source: &str, offset: usize, context: &LanguageContext) -> Option<model::HoverOutput> {
+    let tokens = lexer::tokenize(source);
+    let index = lexer::token_at(&tokens, offset)?;
+    let token = tokens[index];
+
+    match token.kind {
+        TokenKind::EventPath | TokenKind::MetadataPath => {
+            let (kind, root) = if token.kind == TokenKind::EventPath {
+                (&context.target_kind, ".")
+            } else {
+                (&context.metadata_kind, "%")
+            };
+            let inferred_type = if token.text == root {
+                Some(kind.to_string())
+            } else {
+                known_paths(kind, root)
+                    .into_iter()
+                    .find(|(path, _)| path == token.text)
+                    .map(|(_, kind)| kind.to_string())
+            };
+            Some(model::HoverOutput {
+                span: token.span,
+                title: token.text.to_owned(),
+                inferred_type,
+                documentation: None,
+            })
+        }
+        TokenKind::Ident if is_call(&tokens, index) => {
+            let function = context
+                .functions
+                .iter()
+                .find(|function| function.identifier() == token.text)?;
+            Some(model::HoverOutput {
+                span: token.span,
+                title: signature(function.as_ref()),
+                inferred_type: None,
+                documentation: documentation(function.as_ref()),
+            })
+        }
+        TokenKind::Ident if variables(&tokens, source.len()).contains(&token.text.to_owned()) => {
+            Some(model::HoverOutput {
+                span: token.span,
+                title: token.text.to_owned(),
+                inferred_type: context
+                    .variable_kind(token.text)
+                    .map(|kind| kind.to_string()),
+                documentation: Some("local variable".to_owned()),
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Formats a function signature the way the VRL docs do, optional parameters in brackets
+fn signature(function: &dyn Function) -> String {
+    let parameters = function
+        .parameters()
+        .iter()
+        .map(|parameter| {
+            let parameter_signature = format!("{}: {}", parameter.keyword, parameter.kind());
+            if parameter.required {
+                parameter_signature
+            } else {
+                format!("[{}]", parameter_signature)
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(", ");
+    format!("{}({})", function.identifier(), parameters)
+}
+
+/**
+ * The summary and usage of a function, which most stdlib functions leave to the default "TODO",
+ * followed by its examples.
+ */
+fn documentation(function: &dyn Function) -> Option<String> {
+    let mut sections = vec![];
+    for text in [function.summary(), function.usage()] {
+        if text != "TODO" && !text.is_empty() && !sections.iter().any(|section| section == text) {
+            sections.push(text.to_owned());
+        }
+    }
+    for example in function.examples() {
+        let result = match example.result {
+            Ok(result) => result.to_owned(),
+            Err(err) => format!("error: {}", err),
+        };
+        sections.push(format!(
+            "{}:\n    {}\n    => {}",
+            example.title, example.source, result
+        ));
+    }
+    if sections.is_empty() {
+        None
+    } else {
+        Some(sections.join("\n\n"))
+    }
+}
+
+/// True for `name(` and `name!(`
+fn is_call(tokens: &[Token], index: usize) -> bool {
+    match tokens.get(index + 1).map(|token| token.text) {
+        Some("(") => true,
+        Some("!") => tokens.get(index + 2).map(|token| token.text) == Some("("),
+        _ => false,
+    }
+}
+
+/**
+ * Local variables assigned before the offset: `x = ...`, `x, err = ...`
+ * and closure parameters `-> |key, value|`.
+ */
+fn variables(tokens: &[Token], before: usize) -> Vec<String> {
+    let significant: Vec<&Token> = tokens
+        .iter()
+        .filter(|token| !token.is_trivia() && token.span.1 <= before)
+        .collect();
+
+    let mut variables: Vec<String> = vec![];
+    let mut push = |name: &str| {
+        if !KEYWORDS.contains(&name) && !variables.iter().any(|variable| variable == name) {
+            variables.push(name.to_owned());
+        }
+    };
+
+    for (index, token) in significant.iter().enumerate() {
+        if token.kind != TokenKind::Ident {
+            continue;
+        }
+        let next = significant.get(index + 1).map(|token| token.text);
+        let after_next = significant.get(index + 2);
+        let assigned = next == Some("=");
+        let assigned_with_error = next == Some(",")
+            && after_next.map(|token| token.kind) == Some(TokenKind::Ident)
+            && significant.get(index + 3).map(|token| token.text) == Some("=");
+        let assigned_error = index >= 2
+            && significant[index - 1].text == ","
+            && significant[index - 2].kind == TokenKind::Ident
+            && next == Some("=");
+        if assigned || assigned_with_error || assigned_error {
+            push(token.text);
+        }
+    }
+
+    for (index, token) in significant.iter().enumerate() {
+        if token.text != "->" || significant.get(index + 1).map(|token| token.text) != Some("|") {
+            continue;
+        }
+        for parameter in significant[index + 2..].iter() {
+            match parameter.kind {
+                TokenKind::Ident => push(parameter.text),
+                TokenKind::Punct if parameter.text == "," => {}
+                _ => break,
+            }
+        }
+    }
+
+    variables
+}
+
+/// Lists the known paths of a kind, recursively, rooted at `.` for the event or `%` for metadata
+fn known_paths(kind: &Kind, root: &str) -> Vec<(String, Kind)> {
+    let mut paths = vec![];
+    collect_paths(kind, String::new(), MAX_PATH_DEPTH, &mut paths);
+    paths
+        .into_iter()
+        .map(|(path, kind)| {
+            let path = if root == "%" {
+                format!("%{}", &path[1..])
+            } else {
+                path
+            };
+            (path, kind)
+        })
+        .collect()
+}
+
+fn collect_paths(kind: &Kind, prefix: String, depth: usize, paths: &mut Vec<(String, Kind)>) {
+    if depth == 0 {
+        return;
+    }
+    if let Some(fields) = kind.as_object() {
+        for (field, field_kind) in fields.known() {
+            let name = field.to_string();
+            let segment = if !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '@')
+            {
+                name
+            } else {
+                format!("{:?}", name)
+            };
+            let path = format!("{}.{}", prefix, segment);
+            paths.push((path.clone(), field_kind.clone()));
+            collect_paths(field_kind, path, depth - 1, paths);
+        }
+    }
+}
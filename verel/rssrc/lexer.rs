@@ -0,0 +1,240 @@
+//! A lossless tokenizer for VRL source code.
+//!
+//! Unlike the VRL parser, it never fails and keeps comments and whitespace, which is what
+//! editor features need: they run on incomplete programs and must not lose any text.
+//! Concatenating the text of every token gives back the original source.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenKind {
+    Whitespace,
+    Newline,
+    Comment,
+    Ident,
+    Number,
+    /// "..." string, possibly unterminated
+    String,
+    /// s'...', r'...' or t'...' literal
+    RawString,
+    /// event path like `.`, `.foo.bar[0]` or `."quoted field"`
+    EventPath,
+    /// metadata path like `%`, `%foo.bar`
+    MetadataPath,
+    /// `.field` access on a local variable or expression
+    FieldAccess,
+    Operator,
+    Punct,
+    Unknown,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Token<'a> {
+    pub kind: TokenKind,
+    pub text: &'a str,
+    pub span: (usize, usize),
+}
+
+impl<'a> Token<'a> {
+    pub fn is_trivia(&self) -> bool {
+        matches!(
+            self.kind,
+            TokenKind::Whitespace | TokenKind::Newline | TokenKind::Comment
+        )
+    }
+
+    /// True when the token ends an operand, meaning a following `.` or `%` is not a path start
+    pub fn ends_operand(&self) -> bool {
+        match self.kind {
+            TokenKind::Ident => !KEYWORDS.contains(&self.text),
+            TokenKind::Number
+            | TokenKind::String
+            | TokenKind::RawString
+            | TokenKind::EventPath
+            | TokenKind::MetadataPath
+            | TokenKind::FieldAccess => true,
+            TokenKind::Punct => matches!(self.text, ")" | "]"),
+            _ => false,
+        }
+    }
+}
+
+/// Keywords that can be followed by an expression
+const KEYWORDS: &[&str] = &["if", "else", "abort", "return"];
+
+const OPERATORS: &[&str] = &[
+    "==", "!=", ">=", "<=", "&&", "||", "??", "->", "|=", "=", "+", "-", "*", "/", "%", "<", ">",
+    "!", "|",
+];
+
+pub fn tokenize(source: &str) -> Vec<Token<'_>> {
+    let mut tokens: Vec<Token> = vec![];
+    let bytes = source.as_bytes();
+    let mut start = 0;
+
+    while start < source.len() {
+        let rest = &source[start..];
+        let c = rest.chars().next().unwrap_or_default();
+        // a newline ends the statement, so a `.` on the next line always starts a path
+        let previous_operand = tokens
+            .iter()
+            .rev()
+            .find(|token| !matches!(token.kind, TokenKind::Whitespace | TokenKind::Comment))
+            .map(|token| token.ends_operand())
+            .unwrap_or(false);
+
+        let (kind, len) = if c == '\n' {
+            (TokenKind::Newline, 1)
+        } else if c.is_whitespace() {
+            let len = rest
+                .find(|c: char| !c.is_whitespace() || c == '\n')
+                .unwrap_or(rest.len());
+            (TokenKind::Whitespace, len)
+        } else if c == '#' {
+            (TokenKind::Comment, rest.find('\n').unwrap_or(rest.len()))
+        } else if c == '"' {
+            (TokenKind::String, quoted_len(rest, '"'))
+        } else if matches!(c, 's' | 'r' | 't') && bytes.get(start + 1) == Some(&b'\'') {
+            (TokenKind::RawString, 1 + quoted_len(&rest[1..], '\''))
+        } else if is_ident_start(c) {
+            (TokenKind::Ident, ident_len(rest))
+        } else if c.is_ascii_digit() {
+            (TokenKind::Number, number_len(rest))
+        } else if c == '.' && !previous_operand {
+            (TokenKind::EventPath, path_len(rest))
+        } else if c == '%' && !previous_operand {
+            (TokenKind::MetadataPath, path_len(rest))
+        } else if c == '.' {
+            // `.` followed by a field, after an operand
+            let field_len = segment_len(&rest[1..]);
+            (TokenKind::FieldAccess, 1 + field_len)
+        } else if let Some(operator) = OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+            (TokenKind::Operator, operator.len())
+        } else if "(){}[],:;".contains(c) {
+            (TokenKind::Punct, 1)
+        } else {
+            (TokenKind::Unknown, c.len_utf8())
+        };
+
+        let end = start + len.max(c.len_utf8());
+        tokens.push(Token {
+            kind,
+            text: &source[start..end],
+            span: (start, end),
+        });
+        start = end;
+    }
+
+    tokens
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+fn ident_len(source: &str) -> usize {
+    source
+        .find(|c: char| !is_ident_char(c))
+        .unwrap_or(source.len())
+}
+
+fn number_len(source: &str) -> usize {
+    let mut len = 0;
+    let mut seen_dot = false;
+    for (index, c) in source.char_indices() {
+        let next_is_digit = source[index + c.len_utf8()..]
+            .chars()
+            .next()
+            .map(|c| c.is_ascii_digit())
+            .unwrap_or(false);
+        if c.is_ascii_digit() || c == '_' {
+            len = index + 1;
+        } else if c == '.' && !seen_dot && next_is_digit {
+            seen_dot = true;
+            len = index + 1;
+        } else {
+            break;
+        }
+    }
+    len
+}
+
+/// Length of a quoted literal starting with `quote`, including both quotes when terminated
+fn quoted_len(source: &str, quote: char) -> usize {
+    let mut escaped = false;
+    for (index, c) in source.char_indices().skip(1) {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == quote {
+            return index + 1;
+        } else if c == '\n' && quote == '\'' {
+            // raw literals cannot span lines; stop so the rest of the program is still tokenized
+            return index;
+        }
+    }
+    source.len()
+}
+
+/// Length of one path segment: an identifier-like field or a quoted field
+fn segment_len(source: &str) -> usize {
+    match source.chars().next() {
+        Some('"') => quoted_len(source, '"'),
+        Some(c) if is_ident_char(c) || c == '@' => source
+            .find(|c: char| !(is_ident_char(c) || c == '@'))
+            .unwrap_or(source.len()),
+        _ => 0,
+    }
+}
+
+/// Length of a path starting with its `.` or `%` prefix
+fn path_len(source: &str) -> usize {
+    let mut len = 1;
+    // `%foo` and `.foo` start with a segment right after the prefix
+    len += segment_len(&source[len..]);
+    loop {
+        let rest = &source[len..];
+        if let Some(after_dot) = rest.strip_prefix('.') {
+            let segment = segment_len(after_dot);
+            if segment == 0 {
+                break;
+            }
+            len += 1 + segment;
+        } else if rest.starts_with('[') {
+            match rest.find(']') {
+                Some(close)
+                    if rest[1..close]
+                        .trim_start_matches('-')
+                        .chars()
+                        .all(|c| c.is_ascii_digit())
+                        && close > 1 =>
+                {
+                    len += close + 1;
+                }
+                _ => break,
+            }
+        } else {
+            break;
+        }
+    }
+    len
+}
+
+/// Returns the index of the token starting at the offset, or else of the token containing it
+pub fn token_at(tokens: &[Token], offset: usize) -> Option<usize> {
+    tokens
+        .iter()
+        .position(|token| token.span.0 == offset && !token.is_trivia())
+        .or_else(|| token_before(tokens, offset))
+}
+
+/// Returns the index of the token containing the offset or ending right at it, the one being typed
+pub fn token_before(tokens: &[Token], offset: usize) -> Option<usize> {
+    tokens
+        .iter()
+        .position(|token| token.span.0 < offset && offset <= token.span.1)
+        .or_else(|| tokens.iter().position(|token| token.span.0 == offset))
+}
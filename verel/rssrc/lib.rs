@@ -1,115 +1,359 @@
 use gloo_utils::format::JsValueSerdeExt;
-use std::collections::BTreeMap;
-use vrl::compiler::runtime::Runtime;
-use vrl::compiler::TimeZone;
-use vrl::compiler::{compile_with_state, CompileConfig, TargetValue, TypeState};
-use vrl::value::Secrets;
-use vrl::value::Value;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use wasm_bindgen::prelude::*;
 
-mod model;
+mod analysis;
+pub mod api;
+mod cache;
+mod codec;
+mod determinism;
+mod format;
+mod host;
+mod language;
+mod lexer;
+mod limits;
+pub mod model;
+mod position;
+mod render;
+mod sandbox;
+mod schema;
+mod secret;
+mod target;
+mod testing;
+mod timezone;
+mod trace;
 
-fn _compile(input: &model::CompilationInput) -> model::CompilationOutput {
-    let functions = vrl::stdlib::all();
+// ##########
+// ### IO ###
+// ##########
 
-    let state = TypeState::default();
-    let config = CompileConfig::default();
+fn init() {
+    console_error_panic_hook::set_once();
+}
+
+/// Error kinds for what JS passed in and what could not be passed back
+const INVALID_INPUT: &str = "invalid_input";
+const SERIALIZATION: &str = "serialization";
 
-    let compiled = compile_with_state(&input.program, &functions, &state, config);
+fn verel_error(kind: &str, message: &str) -> JsValue {
+    let error = js_sys::Error::new(message);
+    error.set_name("VerelError");
+    // setting a property on a fresh Error object cannot fail
+    let _ = js_sys::Reflect::set(&error, &JsValue::from_str("kind"), &JsValue::from_str(kind));
+    error.into()
+}
 
-    match compiled {
-        Ok(res) => Ok(model::SuccessCompilationOutput {
-            program: res.program,
-            warnings: res
-                .warnings
-                .iter()
-                .map(|d: &vrl::diagnostic::Diagnostic| model::CompilationDiagnostic::from(d))
-                .collect(),
-        }),
-        Err(diagnostics) => Err(model::ErrorCompilationOutput {
-            errors: diagnostics
-                .iter()
-                .map(|d: &vrl::diagnostic::Diagnostic| model::CompilationDiagnostic::from(d))
-                .collect(),
-        }),
+fn js_message(err: &JsValue) -> String {
+    match err.dyn_ref::<js_sys::Error>() {
+        Some(error) => String::from(error.message()),
+        None => err.as_string().unwrap_or_else(|| format!("{:?}", err)),
     }
 }
 
-fn _check(input: &model::CheckInput) -> model::CheckOutput {
-    let compile_output = _compile(&model::CompilationInput {
-        program: input.program.clone(),
-    });
-    model::CheckOutput::from(compile_output)
+/**
+ * Converts a JS value through JSON like `into_serde`, but reports cyclic objects,
+ * BigInt values and mismatching shapes as errors instead of panicking.
+ */
+fn from_js<T: DeserializeOwned>(value: &JsValue, name: &str) -> Result<T, JsValue> {
+    let json = if value.is_undefined() {
+        "null".to_owned()
+    } else {
+        match js_sys::JSON::stringify(value) {
+            // functions and symbols stringify to undefined
+            Ok(json) => json.as_string().unwrap_or_else(|| "null".to_owned()),
+            Err(err) => {
+                let message = format!("{} is not serializable: {}", name, js_message(&err));
+                return Err(verel_error(INVALID_INPUT, &message));
+            }
+        }
+    };
+    serde_json::from_str(&json)
+        .map_err(|err| verel_error(INVALID_INPUT, &format!("invalid {}: {}", name, err)))
 }
 
-fn _execute(input: &model::ExecutionInput) -> model::ExecutionOutput {
-    let compile_output = _compile(&model::CompilationInput {
-        program: input.program.clone(),
-    });
+fn to_js<T: Serialize>(value: &T) -> Result<JsValue, JsValue> {
+    JsValue::from_serde(value).map_err(|err| {
+        verel_error(
+            SERIALIZATION,
+            &format!("unable to serialize the output: {}", err),
+        )
+    })
+}
+
+fn options_from_js<T: DeserializeOwned + Default>(options: &JsValue) -> Result<T, JsValue> {
+    if options.is_undefined() || options.is_null() {
+        return Ok(T::default());
+    }
+    from_js(options, "options")
+}
+
+fn execution_error(err: model::ErrorExecutionOutput) -> JsValue {
+    let summary = err.summary();
+    match err {
+        model::ErrorExecutionOutput::Termination(termination) => {
+            let termination = match to_js(&termination) {
+                Ok(termination) => termination,
+                Err(err) => return err,
+            };
+            let error = js_sys::Error::new(&summary);
+            error.set_name("TerminationError");
+            let _ = js_sys::Reflect::set(&error, &JsValue::from_str("termination"), &termination);
+            error.into()
+        }
+        model::ErrorExecutionOutput::InvalidOption(_)
+        | model::ErrorExecutionOutput::InvalidInput(_) => verel_error(INVALID_INPUT, &summary),
+        model::ErrorExecutionOutput::Serialization(_) => verel_error(SERIALIZATION, &summary),
+        model::ErrorExecutionOutput::CompilationError(_) => JsError::new(&summary).into(),
+    }
+}
 
-    let program: model::CompiledProgram = match compile_output {
-        Ok(output) => output.program,
-        Err(err) => return Err(model::ErrorExecutionOutput::CompilationError(err)),
+#[wasm_bindgen]
+pub fn check(program: String, options: JsValue) -> Result<JsValue, JsValue> {
+    init();
+    let check_input = model::CheckInput {
+        program,
+        options: options_from_js(&options)?,
     };
+    match api::check(&check_input) {
+        Ok(check_output) => to_js(&check_output),
+        Err(err) => Err(verel_error(INVALID_INPUT, &err)),
+    }
+}
 
-    let mut runtime = Runtime::default();
-    let timezone = TimeZone::default();
+#[wasm_bindgen]
+pub fn typecheck(program: String, options: JsValue) -> Result<JsValue, JsValue> {
+    init();
+    let check_input = model::CheckInput {
+        program,
+        options: options_from_js(&options)?,
+    };
+    match api::typecheck(&check_input) {
+        Ok(typecheck_output) => to_js(&typecheck_output),
+        Err(err) => Err(verel_error(INVALID_INPUT, &err)),
+    }
+}
 
-    let mut target_value = TargetValue {
-        value: input.event.clone(),
-        metadata: Value::Object(BTreeMap::new()),
-        secrets: Secrets::new(),
+#[wasm_bindgen]
+pub fn analyze(program: String, options: JsValue) -> Result<JsValue, JsValue> {
+    init();
+    let check_input = model::CheckInput {
+        program,
+        options: options_from_js(&options)?,
     };
+    match api::analyze(&check_input) {
+        Ok(analysis_output) => to_js(&analysis_output),
+        Err(err) => Err(verel_error(INVALID_INPUT, &err)),
+    }
+}
 
-    let resolved = runtime.resolve(&mut target_value, &program, &timezone);
-    match resolved {
-        Ok(res) => Ok(model::SuccessExecutionOutput {
-            event: target_value.value,
-            result: res,
-        }),
-        Err(terminate) => Err(model::ErrorExecutionOutput::Termination(
-            model::ExecutionTermination::from(&terminate),
-        )),
+#[wasm_bindgen]
+pub fn execute(program: String, event: JsValue, options: JsValue) -> Result<JsValue, JsValue> {
+    init();
+    let execute_input = model::ExecutionInput {
+        program,
+        event: from_js(&event, "event")?,
+        options: options_from_js(&options)?,
+    };
+    let execute_output = api::execute(&execute_input);
+    match execute_output {
+        Ok(output) => to_js(&output),
+        Err(err) => Err(execution_error(err)),
     }
 }
 
-/**
- * ##########
- * ### IO ###
- * ##########
- */
+#[wasm_bindgen]
+pub fn execute_batch(
+    program: String,
+    events: JsValue,
+    options: JsValue,
+) -> Result<JsValue, JsValue> {
+    init();
+    let execute_input = model::BatchExecutionInput {
+        program,
+        events: from_js(&events, "events")?,
+        options: options_from_js(&options)?,
+    };
+    let execute_output = api::execute_batch(&execute_input);
+    match execute_output {
+        Ok(output) => to_js(&output),
+        Err(err) => Err(execution_error(err)),
+    }
+}
 
-fn init() {
-    console_error_panic_hook::set_once();
+#[wasm_bindgen]
+pub fn run_tests(program: String, cases: JsValue, options: JsValue) -> Result<JsValue, JsValue> {
+    init();
+    let test_input = model::TestInput {
+        program,
+        cases: from_js(&cases, "test cases")?,
+        options: options_from_js(&options)?,
+    };
+    match api::test(&test_input) {
+        Ok(output) => to_js(&output),
+        Err(err) => Err(execution_error(err)),
+    }
 }
 
 #[wasm_bindgen]
-pub fn check(program: String) -> JsValue {
+pub fn format_diagnostic(input: JsValue, options: JsValue) -> Result<String, JsValue> {
     init();
-    let check_input = model::CheckInput { program };
-    let check_output = _check(&check_input);
-    JsValue::from_serde(&check_output).unwrap()
+    let options: model::FormatDiagnosticOptions = options_from_js(&options)?;
+    let diagnostic: model::CompilationDiagnostic = from_js(&input, "diagnostic")?;
+    api::format_diagnostic(&diagnostic, &options).map_err(|err| JsError::new(&err).into())
 }
 
 #[wasm_bindgen]
-pub fn execute(program: String, event: JsValue) -> Result<JsValue, JsError> {
+pub fn format(program: String) -> Result<JsValue, JsValue> {
     init();
-    let execute_input = model::ExecutionInput {
+    let format_output = api::format(&program);
+    to_js(&format_output)
+}
+
+#[wasm_bindgen]
+pub fn complete(program: String, offset: usize, options: JsValue) -> Result<JsValue, JsValue> {
+    init();
+    let language_input = model::LanguageInput {
         program,
-        event: event.into_serde().unwrap(),
+        offset,
+        options: options_from_js(&options)?,
     };
-    let execute_output = _execute(&execute_input);
-    match execute_output {
-        Ok(output) => Ok(JsValue::from_serde(&output).unwrap()),
-        Err(err) => Err(JsError::new(&err.summary())),
+    match api::complete(&language_input) {
+        Ok(output) => to_js(&output),
+        Err(err) => Err(verel_error(INVALID_INPUT, &err)),
+    }
+}
+
+#[wasm_bindgen]
+pub fn hover(program: String, offset: usize, options: JsValue) -> Result<JsValue, JsValue> {
+    init();
+    let language_input = model::LanguageInput {
+        program,
+        offset,
+        options: options_from_js(&options)?,
+    };
+    match api::hover(&language_input) {
+        Ok(output) => to_js(&output),
+        Err(err) => Err(verel_error(INVALID_INPUT, &err)),
+    }
+}
+
+#[wasm_bindgen]
+pub fn register_function(definition: JsValue, callback: js_sys::Function) -> Result<(), JsValue> {
+    init();
+    let definition: model::HostFunctionDefinition = from_js(&definition, "function definition")?;
+    host::register(definition, callback).map_err(|err| JsError::new(&err).into())
+}
+
+#[wasm_bindgen]
+pub fn unregister_function(name: String) -> bool {
+    init();
+    host::unregister(&name)
+}
+
+#[wasm_bindgen]
+pub fn configure_cache(options: JsValue) -> Result<(), JsValue> {
+    init();
+    let options: model::CacheOptions = from_js(&options, "cache options")?;
+    cache::configure(&options);
+    Ok(())
+}
+
+#[wasm_bindgen]
+pub fn clear_cache() {
+    init();
+    cache::clear();
+}
+
+#[wasm_bindgen]
+pub fn cache_stats() -> Result<JsValue, JsValue> {
+    init();
+    to_js(&cache::stats())
+}
+
+#[wasm_bindgen(js_name = CompiledProgram)]
+pub struct CompiledProgramHandle {
+    program: api::Program,
+}
+
+#[wasm_bindgen(js_class = CompiledProgram)]
+impl CompiledProgramHandle {
+    pub fn warnings(&self) -> Result<JsValue, JsValue> {
+        to_js(&self.program.warnings())
+    }
+
+    pub fn execute(&mut self, event: JsValue, options: JsValue) -> Result<JsValue, JsValue> {
+        let options: model::ExecutionOptions = options_from_js(&options)?;
+        let execute_output = self.program.execute(from_js(&event, "event")?, &options);
+        match execute_output {
+            Ok(output) => to_js(&output),
+            Err(err) => Err(execution_error(err)),
+        }
+    }
+}
+
+#[wasm_bindgen]
+pub fn compile(program: String, options: JsValue) -> Result<CompiledProgramHandle, JsValue> {
+    init();
+    let options: model::CompilationOptions = options_from_js(&options)?;
+    match api::Program::new(program, &options) {
+        Ok(program) => Ok(CompiledProgramHandle { program }),
+        Err(err) => Err(execution_error(
+            model::ErrorExecutionOutput::CompilationError(err),
+        )),
+    }
+}
+
+#[wasm_bindgen(js_name = Router)]
+pub struct RouterHandle {
+    router: api::Router,
+}
+
+#[wasm_bindgen(js_class = Router)]
+impl RouterHandle {
+    pub fn route(&mut self, event: JsValue, options: JsValue) -> Result<JsValue, JsValue> {
+        let options: model::ExecutionOptions = options_from_js(&options)?;
+        let event: model::ExecutionEvent = from_js(&event, "event")?;
+        match self.router.route(&event, &options) {
+            Ok(output) => to_js(&output),
+            Err(err) => Err(execution_error(err)),
+        }
+    }
+}
+
+#[wasm_bindgen]
+pub fn create_router(routes: JsValue, options: JsValue) -> Result<RouterHandle, JsValue> {
+    init();
+    let routes: model::Routes = from_js(&routes, "routes")?;
+    let options: model::CompilationOptions = options_from_js(&options)?;
+    match api::Router::new(&routes, &options) {
+        Ok(router) => Ok(RouterHandle { router }),
+        Err(err) => Err(JsError::new(&err.summary()).into()),
+    }
+}
+
+#[wasm_bindgen(js_name = Transformer)]
+pub struct TransformerHandle {
+    transformer: api::Transformer,
+}
+
+#[wasm_bindgen(js_class = Transformer)]
+impl TransformerHandle {
+    pub fn push(&mut self, chunk: &[u8]) -> Result<JsValue, JsValue> {
+        to_js(&self.transformer.push(chunk))
+    }
+
+    pub fn finish(&mut self) -> Result<JsValue, JsValue> {
+        to_js(&self.transformer.finish())
     }
 }
 
 #[wasm_bindgen]
-pub fn format_diagnostic(input: JsValue) -> Result<String, JsError> {
+pub fn create_transformer(program: String, options: JsValue) -> Result<TransformerHandle, JsValue> {
     init();
-    match input.into_serde::<model::CompilationDiagnostic>() {
-        Ok(diagnostic) => Ok(diagnostic.summary()),
-        Err(err) => Err(JsError::new(&err.to_string())),
+    let options: model::ExecutionOptions = options_from_js(&options)?;
+    match api::Transformer::new(program, options) {
+        Ok(transformer) => Ok(TransformerHandle { transformer }),
+        Err(err) => Err(execution_error(err)),
     }
 }
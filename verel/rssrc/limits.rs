@@ -0,0 +1,424 @@
+use crate::model;
+use std::cell::RefCell;
+use vrl::compiler::function::closure;
+use vrl::compiler::{CompileConfig, Compiler, Program, TypeInfo};
+use vrl::diagnostic::Span;
+use vrl::parser::ast::{
+    Assignment, AssignmentTarget, Container, Expr, FunctionCall, Group, Ident, IfStatement, Node,
+    Not, Op, Predicate, Query, QueryTarget, RootExpr, Unary,
+};
+use vrl::prelude::*;
+
+/// Not a valid VRL identifier, so programs cannot call it themselves
+const ITERATION_METER: &str = "$meter_iteration";
+
+/**
+ * VRL has no loop construct: a program can only iterate through the closures of functions
+ * like for_each, map_values or filter. Metering every function call and every closure
+ * iteration, and checking the deadline each time, is therefore enough to interrupt a
+ * runaway program without being able to preempt the synchronous Runtime::resolve.
+ */
+struct Budget {
+    max_function_calls: Option<u64>,
+    function_calls: u64,
+    deadline_ms: Option<f64>,
+    exceeded: Option<String>,
+}
+
+thread_local! {
+    static BUDGET: RefCell<Option<Budget>> = const { RefCell::new(None) };
+}
+
+pub fn start(limits: &model::ExecutionLimits) {
+    let budget = Budget {
+        max_function_calls: limits.max_function_calls,
+        function_calls: 0,
+        deadline_ms: limits.max_duration_ms.map(|duration| now_ms() + duration),
+        exceeded: None,
+    };
+    BUDGET.with(|cell| *cell.borrow_mut() = Some(budget));
+}
+
+/// Returns the reason the budget was exceeded, if it was, and clears it
+pub fn finish() -> Option<String> {
+    BUDGET.with(|cell| cell.borrow_mut().take().and_then(|budget| budget.exceeded))
+}
+
+pub fn check_output(value: &Value, limits: &model::ExecutionLimits) -> Result<(), String> {
+    if let Some(max_depth) = limits.max_depth {
+        let depth = value_depth(value);
+        if depth > max_depth {
+            return Err(format!(
+                "output nesting depth of {} exceeds the limit of {}",
+                depth, max_depth
+            ));
+        }
+    }
+
+    if let Some(max_output_bytes) = limits.max_output_bytes {
+        let output_bytes = serde_json::to_vec(value)
+            .map(|bytes| bytes.len())
+            .unwrap_or(usize::MAX);
+        if output_bytes > max_output_bytes {
+            return Err(format!(
+                "output size of {} bytes exceeds the limit of {} bytes",
+                output_bytes, max_output_bytes
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+fn value_depth(value: &Value) -> usize {
+    match value {
+        Value::Object(fields) => 1 + fields.values().map(value_depth).max().unwrap_or(0),
+        Value::Array(items) => 1 + items.iter().map(value_depth).max().unwrap_or(0),
+        _ => 0,
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn now_ms() -> f64 {
+    js_sys::Date::now()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn now_ms() -> f64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs_f64() * 1000.0)
+        .unwrap_or(0.0)
+}
+
+fn consume() -> Result<(), ExpressionError> {
+    BUDGET.with(|cell| {
+        let mut cell = cell.borrow_mut();
+        let budget = match cell.as_mut() {
+            Some(budget) => budget,
+            None => return Ok(()),
+        };
+
+        if budget.exceeded.is_none() {
+            budget.function_calls += 1;
+            if let Some(max_function_calls) = budget.max_function_calls {
+                if budget.function_calls > max_function_calls {
+                    budget.exceeded = Some(format!(
+                        "execution exceeded the budget of {} function calls",
+                        max_function_calls
+                    ));
+                }
+            }
+        }
+
+        if budget.exceeded.is_none() {
+            if let Some(deadline_ms) = budget.deadline_ms {
+                if now_ms() > deadline_ms {
+                    budget.exceeded = Some("execution exceeded its time limit".to_owned());
+                }
+            }
+        }
+
+        match &budget.exceeded {
+            Some(reason) => Err(ExpressionError::from(reason.clone())),
+            None => Ok(()),
+        }
+    })
+}
+
+/// Meters the calls to the functions, and adds the meter `meter_closures` calls in closures
+pub fn instrument(functions: Vec<Box<dyn Function>>) -> Vec<Box<dyn Function>> {
+    functions
+        .into_iter()
+        .map(|function| Box::new(MeteredFunction { inner: function }) as Box<dyn Function>)
+        .chain(std::iter::once(
+            Box::new(IterationMeter) as Box<dyn Function>
+        ))
+        .collect()
+}
+
+/**
+ * Compiles a program again with a call to the iteration meter at the start of every closure,
+ * since VRL runs closures without a hook. Returns `None` when the program has no closure.
+ *
+ * The program must have compiled as it is, which reports its diagnostics.
+ */
+pub fn meter_closures(
+    source: &str,
+    functions: &[Box<dyn Function>],
+    state: &TypeState,
+) -> Option<Program> {
+    let program = vrl::parser::parse(source).ok()?;
+    let mut metered = false;
+    let roots = program
+        .0
+        .into_iter()
+        .map(|root| {
+            root.map(|root| match root {
+                RootExpr::Expr(expr) => RootExpr::Expr(meter_expr(expr, &mut metered)),
+                error => error,
+            })
+        })
+        .collect();
+    if !metered {
+        return None;
+    }
+
+    let ast = vrl::parser::ast::Program(roots);
+    Compiler::compile(functions, ast, state, CompileConfig::default())
+        .ok()
+        .map(|result| result.program)
+}
+
+fn meter_expr(expr: Node<Expr>, metered: &mut bool) -> Node<Expr> {
+    let (span, expr) = expr.take();
+    let expr = match expr {
+        Expr::Literal(_) | Expr::Variable(_) => expr,
+        Expr::Container(container) => {
+            Expr::Container(container.map(|container| meter_container(container, metered)))
+        }
+        Expr::IfStatement(statement) => Expr::IfStatement(statement.map(|statement| {
+            IfStatement {
+                predicate: statement.predicate.map(|predicate| match predicate {
+                    Predicate::One(expr) => Predicate::One(Box::new(meter_expr(*expr, metered))),
+                    Predicate::Many(exprs) => Predicate::Many(meter_exprs(exprs, metered)),
+                }),
+                if_node: statement
+                    .if_node
+                    .map(|block| vrl::parser::ast::Block(meter_exprs(block.0, metered))),
+                else_node: statement.else_node.map(|block| {
+                    block.map(|block| vrl::parser::ast::Block(meter_exprs(block.0, metered)))
+                }),
+            }
+        })),
+        Expr::Op(op) => Expr::Op(op.map(|Op(left, opcode, right)| {
+            Op(
+                Box::new(meter_expr(*left, metered)),
+                opcode,
+                Box::new(meter_expr(*right, metered)),
+            )
+        })),
+        Expr::Assignment(assignment) => {
+            Expr::Assignment(assignment.map(|assignment| match assignment {
+                Assignment::Single { target, op, expr } => Assignment::Single {
+                    target: target.map(|target| meter_assignment_target(target, metered)),
+                    op,
+                    expr: Box::new(meter_expr(*expr, metered)),
+                },
+                Assignment::Infallible { ok, err, op, expr } => Assignment::Infallible {
+                    ok: ok.map(|target| meter_assignment_target(target, metered)),
+                    err: err.map(|target| meter_assignment_target(target, metered)),
+                    op,
+                    expr: Box::new(meter_expr(*expr, metered)),
+                },
+            }))
+        }
+        Expr::Query(query) => Expr::Query(query.map(|query| meter_query(query, metered))),
+        Expr::FunctionCall(call) => Expr::FunctionCall(call.map(|call| meter_call(call, metered))),
+        Expr::Unary(unary) => Expr::Unary(unary.map(|Unary::Not(not)| {
+            Unary::Not(not.map(|not| {
+                let (operator, operand) = not.take();
+                Not::new(operator.span(), meter_expr(*operand, metered))
+            }))
+        })),
+        Expr::Abort(abort) => Expr::Abort(abort.map(|mut abort| {
+            abort.message = abort
+                .message
+                .map(|message| Box::new(meter_expr(*message, metered)));
+            abort
+        })),
+        Expr::Return(ret) => Expr::Return(ret.map(|mut ret| {
+            ret.expr = Box::new(meter_expr(*ret.expr, metered));
+            ret
+        })),
+    };
+    Node::new(span, expr)
+}
+
+fn meter_exprs(exprs: Vec<Node<Expr>>, metered: &mut bool) -> Vec<Node<Expr>> {
+    exprs
+        .into_iter()
+        .map(|expr| meter_expr(expr, metered))
+        .collect()
+}
+
+fn meter_call(mut call: FunctionCall, metered: &mut bool) -> FunctionCall {
+    call.arguments = call
+        .arguments
+        .into_iter()
+        .map(|argument| {
+            argument.map(|mut argument| {
+                argument.expr = meter_expr(argument.expr, metered);
+                argument
+            })
+        })
+        .collect();
+    call.closure = call.closure.map(|closure| {
+        closure.map(|mut closure| {
+            *metered = true;
+            let start = closure.block.start();
+            let at_start = Span::new(start, start);
+            closure.block = closure.block.map(|block| {
+                let meter = Expr::FunctionCall(Node::new(
+                    at_start,
+                    FunctionCall {
+                        ident: Node::new(at_start, Ident::new(ITERATION_METER)),
+                        abort_on_error: false,
+                        arguments: vec![],
+                        closure: None,
+                    },
+                ));
+                let exprs = std::iter::once(Node::new(at_start, meter))
+                    .chain(meter_exprs(block.0, metered))
+                    .collect();
+                vrl::parser::ast::Block(exprs)
+            });
+            closure
+        })
+    });
+    call
+}
+
+fn meter_container(container: Container, metered: &mut bool) -> Container {
+    match container {
+        Container::Group(group) => Container::Group(Box::new(
+            group.map(|group| Group(meter_expr(group.into_inner(), metered))),
+        )),
+        Container::Block(block) => Container::Block(
+            block.map(|block| vrl::parser::ast::Block(meter_exprs(block.0, metered))),
+        ),
+        Container::Array(array) => Container::Array(array.map(|array| {
+            array
+                .into_iter()
+                .map(|expr| meter_expr(expr, metered))
+                .collect()
+        })),
+        Container::Object(object) => Container::Object(object.map(|object| {
+            object
+                .into_iter()
+                .map(|(key, expr)| (key, meter_expr(expr, metered)))
+                .collect()
+        })),
+    }
+}
+
+fn meter_query(mut query: Query, metered: &mut bool) -> Query {
+    query.target = query.target.map(|target| match target {
+        QueryTarget::FunctionCall(call) => QueryTarget::FunctionCall(meter_call(call, metered)),
+        QueryTarget::Container(container) => {
+            QueryTarget::Container(meter_container(container, metered))
+        }
+        target => target,
+    });
+    query
+}
+
+fn meter_assignment_target(target: AssignmentTarget, metered: &mut bool) -> AssignmentTarget {
+    match target {
+        AssignmentTarget::Query(query) => AssignmentTarget::Query(meter_query(query, metered)),
+        target => target,
+    }
+}
+
+#[derive(Debug)]
+struct MeteredFunction {
+    inner: Box<dyn Function>,
+}
+
+impl Function for MeteredFunction {
+    fn identifier(&self) -> &'static str {
+        self.inner.identifier()
+    }
+
+    fn summary(&self) -> &'static str {
+        self.inner.summary()
+    }
+
+    fn usage(&self) -> &'static str {
+        self.inner.usage()
+    }
+
+    fn examples(&self) -> &'static [Example] {
+        self.inner.examples()
+    }
+
+    fn parameters(&self) -> &'static [Parameter] {
+        self.inner.parameters()
+    }
+
+    fn closure(&self) -> Option<closure::Definition> {
+        self.inner.closure()
+    }
+
+    fn compile(
+        &self,
+        state: &TypeState,
+        ctx: &mut FunctionCompileContext,
+        arguments: ArgumentList,
+    ) -> Compiled {
+        let inner = self.inner.compile(state, ctx, arguments)?;
+        Ok(Box::new(MeteredExpression { inner }))
+    }
+}
+
+#[derive(Clone, Debug)]
+struct MeteredExpression {
+    inner: Box<dyn Expression>,
+}
+
+impl Expression for MeteredExpression {
+    fn resolve(&self, ctx: &mut Context) -> Resolved {
+        consume()?;
+        self.inner.resolve(ctx)
+    }
+
+    fn resolve_constant(&self, state: &TypeState) -> Option<Value> {
+        self.inner.resolve_constant(state)
+    }
+
+    fn type_info(&self, state: &TypeState) -> TypeInfo {
+        self.inner.type_info(state)
+    }
+
+    fn type_def(&self, state: &TypeState) -> TypeDef {
+        self.inner.type_def(state)
+    }
+}
+
+/// Called at the start of every closure by the programs `meter_closures` compiled
+#[derive(Debug)]
+struct IterationMeter;
+
+impl Function for IterationMeter {
+    fn identifier(&self) -> &'static str {
+        ITERATION_METER
+    }
+
+    fn examples(&self) -> &'static [Example] {
+        &[]
+    }
+
+    fn compile(
+        &self,
+        _state: &TypeState,
+        _ctx: &mut FunctionCompileContext,
+        _arguments: ArgumentList,
+    ) -> Compiled {
+        Ok(IterationMeterFn.as_expr())
+    }
+}
+
+#[derive(Clone, Debug)]
+struct IterationMeterFn;
+
+impl FunctionExpression for IterationMeterFn {
+    fn resolve(&self, _ctx: &mut Context) -> Resolved {
+        consume()?;
+        Ok(Value::Null)
+    }
+
+    fn type_def(&self, _state: &TypeState) -> TypeDef {
+        TypeDef::null().infallible()
+    }
+}
@@ -0,0 +1,126 @@
+use std::io::{BufRead, Write};
+use std::process::ExitCode;
+use verel::{api, model};
+
+const USAGE: &str =
+    "usage: verel <program.vrl> [--timezone <name>] [--untrusted] [--clock <timestamp>]
+
+Runs a VRL program on the newline-delimited JSON events read from stdin and writes
+the resulting events to stdout, one per line. Failed events are reported on stderr.
+--clock runs the program deterministically, with now() returning the given RFC 3339 timestamp.";
+
+struct Arguments {
+    program_path: String,
+    options: model::ExecutionOptions,
+}
+
+fn parse_arguments() -> Result<Arguments, String> {
+    let mut program_path = None;
+    let mut options = model::ExecutionOptions::default();
+
+    let mut arguments = std::env::args().skip(1);
+    while let Some(argument) = arguments.next() {
+        match argument.as_str() {
+            "--timezone" => {
+                let timezone = arguments.next().ok_or("missing value for --timezone")?;
+                options.timezone = Some(timezone);
+            }
+            "--untrusted" => options.sandbox.profile = model::SandboxProfile::Untrusted,
+            "--clock" => {
+                let clock = arguments.next().ok_or("missing value for --clock")?;
+                options.clock = Some(clock);
+                options.sandbox.deterministic = true;
+            }
+            "-h" | "--help" => return Err(USAGE.to_owned()),
+            _ if argument.starts_with("--") => {
+                return Err(format!("unknown option {}\n\n{}", argument, USAGE))
+            }
+            _ if program_path.is_none() => program_path = Some(argument),
+            _ => return Err(format!("unexpected argument {}\n\n{}", argument, USAGE)),
+        }
+    }
+
+    Ok(Arguments {
+        program_path: program_path.ok_or(USAGE)?,
+        options,
+    })
+}
+
+fn main() -> ExitCode {
+    let arguments = match parse_arguments() {
+        Ok(arguments) => arguments,
+        Err(err) => {
+            eprintln!("{}", err);
+            return ExitCode::from(2);
+        }
+    };
+
+    let source = match std::fs::read_to_string(&arguments.program_path) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("unable to read {}: {}", arguments.program_path, err);
+            return ExitCode::from(2);
+        }
+    };
+
+    let mut transformer = match api::Transformer::new(source.clone(), arguments.options) {
+        Ok(transformer) => transformer,
+        Err(model::ErrorExecutionOutput::CompilationError(err)) => {
+            let format_options = model::FormatDiagnosticOptions {
+                source: Some(source),
+                color: false,
+                file_name: arguments.program_path,
+            };
+            for diagnostic in err.errors.iter() {
+                match api::format_diagnostic(diagnostic, &format_options) {
+                    Ok(rendered) => eprintln!("{}", rendered),
+                    Err(_) => eprintln!("{}", diagnostic.summary()),
+                }
+            }
+            return ExitCode::from(2);
+        }
+        Err(err) => {
+            eprintln!("{}", err.summary());
+            return ExitCode::from(2);
+        }
+    };
+
+    let stdout = std::io::stdout();
+    let mut stdout = stdout.lock();
+    let mut stdin = std::io::stdin().lock();
+    let mut failures = 0;
+    loop {
+        let chunk = match stdin.fill_buf() {
+            Ok(chunk) => chunk,
+            Err(err) => {
+                eprintln!("unable to read stdin: {}", err);
+                return ExitCode::from(2);
+            }
+        };
+        let (output, consumed) = if chunk.is_empty() {
+            (transformer.finish(), None)
+        } else {
+            (transformer.push(chunk), Some(chunk.len()))
+        };
+
+        for error in output.errors.iter() {
+            eprintln!("line {}: {}", error.line, error.message);
+        }
+        failures += output.errors.len();
+        if let Err(err) = stdout.write_all(output.output.as_bytes()) {
+            eprintln!("unable to write events: {}", err);
+            return ExitCode::from(2);
+        }
+
+        match consumed {
+            Some(consumed) => stdin.consume(consumed),
+            None => break,
+        }
+    }
+
+    if failures > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
@@ -1,20 +1,52 @@
+use crate::position::LineIndex;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-/**
- * ######################
- * ### 0. Compilation ###
- * ######################
- */
+// ######################
+// ### 0. Compilation ###
+// ######################
+
+#[derive(Deserialize, Serialize, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum SandboxProfile {
+    #[default]
+    Full,
+    Untrusted,
+}
+
+#[derive(Deserialize, Serialize, Default, Clone)]
+#[serde(default)]
+pub struct SandboxOptions {
+    pub profile: SandboxProfile,
+    pub allow: Option<Vec<String>>,
+    pub deny: Option<Vec<String>>,
+    /// forbids random, host and network functions, makes `now()` return the `clock` execution option
+    /// (then required) and defaults the timezone to UTC, so executions can be replayed
+    pub deterministic: bool,
+}
 
 pub struct CompilationInput {
     pub program: String,
+    pub state: vrl::compiler::TypeState,
+    pub sandbox: SandboxOptions,
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, Clone, Copy)]
+pub struct SourcePosition {
+    pub line: usize,
+    pub column: usize,
+    pub column_utf16: usize,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
 pub struct CompilationDiagnosticLabel {
     pub message: String,
     pub primary: bool,
     pub span: (usize, usize),
+    #[serde(default)]
+    pub start: Option<SourcePosition>,
+    #[serde(default)]
+    pub end: Option<SourcePosition>,
 }
 impl From<&vrl::diagnostic::Label> for CompilationDiagnosticLabel {
     fn from(label: &vrl::diagnostic::Label) -> Self {
@@ -22,16 +54,23 @@ impl From<&vrl::diagnostic::Label> for CompilationDiagnosticLabel {
             message: label.message.clone(),
             primary: label.primary,
             span: (label.span.start(), label.span.end()),
+            start: None,
+            end: None,
         }
     }
 }
 impl CompilationDiagnosticLabel {
+    pub fn locate(&mut self, line_index: &LineIndex) {
+        self.start = Some(line_index.position(self.span.0));
+        self.end = Some(line_index.position(self.span.1));
+    }
+
     pub fn summary(&self) -> String {
         format!("[{}, {}]: {}", self.span.0, self.span.1, self.message)
     }
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, Clone)]
 pub struct CompilationDiagnosticNote {
     pub message: String,
     pub note_type: String,
@@ -96,13 +135,17 @@ impl CompilationDiagnosticNote {
     }
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, Clone)]
 pub struct CompilationDiagnostic {
     pub message: String,
     pub code: usize,
     pub severity: String,
     pub labels: Vec<CompilationDiagnosticLabel>,
     pub notes: Vec<CompilationDiagnosticNote>,
+    #[serde(default)]
+    pub start: Option<SourcePosition>,
+    #[serde(default)]
+    pub end: Option<SourcePosition>,
 }
 impl From<&vrl::diagnostic::Diagnostic> for CompilationDiagnostic {
     fn from(diagnostic: &vrl::diagnostic::Diagnostic) -> Self {
@@ -120,19 +163,38 @@ impl From<&vrl::diagnostic::Diagnostic> for CompilationDiagnostic {
             labels: diagnostic
                 .labels
                 .iter()
-                .map(|label| CompilationDiagnosticLabel::from(label))
+                .map(CompilationDiagnosticLabel::from)
                 .collect(),
             notes: diagnostic
                 .notes
                 .iter()
-                .map(|note| CompilationDiagnosticNote::from(note))
+                .map(CompilationDiagnosticNote::from)
                 .collect(),
+            start: None,
+            end: None,
         }
     }
 }
 impl CompilationDiagnostic {
+    /// Fills the line/column positions of the labels and of the primary label
+    pub fn locate(mut self, line_index: &LineIndex) -> Self {
+        for label in self.labels.iter_mut() {
+            label.locate(line_index);
+        }
+        let primary = self
+            .labels
+            .iter()
+            .find(|label| label.primary)
+            .or_else(|| self.labels.first());
+        if let Some(primary) = primary {
+            self.start = primary.start;
+            self.end = primary.end;
+        }
+        self
+    }
+
     fn _labels_lines(&self) -> Vec<String> {
-        if self.labels.len() == 0 {
+        if self.labels.is_empty() {
             return vec![];
         }
 
@@ -146,7 +208,7 @@ impl CompilationDiagnostic {
     }
 
     fn _notes_lines(&self) -> Vec<String> {
-        if self.notes.len() == 0 {
+        if self.notes.is_empty() {
             return vec![];
         }
 
@@ -178,12 +240,30 @@ impl CompilationDiagnostic {
     }
 }
 
+#[derive(Deserialize, Serialize)]
+#[serde(default)]
+pub struct FormatDiagnosticOptions {
+    pub source: Option<String>,
+    pub color: bool,
+    pub file_name: String,
+}
+impl Default for FormatDiagnosticOptions {
+    fn default() -> Self {
+        FormatDiagnosticOptions {
+            source: None,
+            color: false,
+            file_name: "program.vrl".to_owned(),
+        }
+    }
+}
+
 pub type CompiledProgram = vrl::compiler::Program;
 pub struct SuccessCompilationOutput {
     pub program: CompiledProgram,
     pub warnings: Vec<CompilationDiagnostic>,
 }
 
+#[derive(Clone)]
 pub struct ErrorCompilationOutput {
     pub errors: Vec<CompilationDiagnostic>,
 }
@@ -200,14 +280,117 @@ impl ErrorCompilationOutput {
 
 pub type CompilationOutput = Result<SuccessCompilationOutput, ErrorCompilationOutput>;
 
-/**
- * ####################
- * ### 1. Check ###
- * ####################
- */
+#[derive(Deserialize, Serialize, Default)]
+#[serde(default)]
+pub struct CompilationOptions {
+    pub sandbox: SandboxOptions,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum HostKind {
+    Single(String),
+    Many(Vec<String>),
+}
+impl Default for HostKind {
+    fn default() -> Self {
+        HostKind::Single("any".to_owned())
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct HostFunctionParameter {
+    pub keyword: String,
+    #[serde(default)]
+    pub kind: HostKind,
+    #[serde(default)]
+    pub required: bool,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct HostFunctionDefinition {
+    pub name: String,
+    #[serde(default)]
+    pub parameters: Vec<HostFunctionParameter>,
+    #[serde(default)]
+    pub return_kind: HostKind,
+}
+
+// ####################
+// ### 1. Check ###
+// ####################
+
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum TypeSchemaType {
+    Single(String),
+    Many(Vec<String>),
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum AdditionalProperties {
+    Allowed(bool),
+    Schema(Box<TypeSchema>),
+}
+
+/// A JSON-schema-like description of a value ("type", "properties", "required", "additionalProperties" and "items")
+#[derive(Deserialize, Serialize, Default)]
+#[serde(default)]
+pub struct TypeSchema {
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub schema_type: Option<TypeSchemaType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub properties: Option<BTreeMap<String, TypeSchema>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub required: Option<Vec<String>>,
+    #[serde(
+        rename = "additionalProperties",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub additional_properties: Option<AdditionalProperties>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub items: Option<Box<TypeSchema>>,
+}
+
+#[derive(Deserialize, Serialize, Default)]
+#[serde(default)]
+pub struct CheckOptions {
+    pub event_schema: Option<TypeSchema>,
+    pub metadata_schema: Option<TypeSchema>,
+    pub sandbox: SandboxOptions,
+    /// warning codes reported as errors
+    pub promote: Vec<usize>,
+    /// warning codes left out, unless promoted
+    pub suppress: Vec<usize>,
+    /// warnings below this severity are left out
+    pub min_severity: DiagnosticSeverity,
+}
+
+#[derive(Deserialize, Serialize, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum DiagnosticSeverity {
+    #[default]
+    Note,
+    Warning,
+    Error,
+    Bug,
+}
+impl DiagnosticSeverity {
+    /// Reads the `severity` of a `CompilationDiagnostic`
+    pub fn parse(severity: &str) -> Self {
+        match severity {
+            "note" => DiagnosticSeverity::Note,
+            "warning" => DiagnosticSeverity::Warning,
+            "error" => DiagnosticSeverity::Error,
+            _ => DiagnosticSeverity::Bug,
+        }
+    }
+}
 
 pub struct CheckInput {
     pub program: String,
+    pub options: CheckOptions,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -215,41 +398,155 @@ pub struct CheckOutput {
     pub warnings: Vec<CompilationDiagnostic>,
     pub errors: Vec<CompilationDiagnostic>,
 }
-impl From<CompilationOutput> for CheckOutput {
-    fn from(output: CompilationOutput) -> Self {
+impl From<&CompilationOutput> for CheckOutput {
+    fn from(output: &CompilationOutput) -> Self {
         match output {
             Ok(success) => CheckOutput {
-                warnings: success.warnings,
+                warnings: success.warnings.clone(),
                 errors: vec![],
             },
             Err(error) => CheckOutput {
                 warnings: vec![],
-                errors: error.errors,
+                errors: error.errors.clone(),
             },
         }
     }
 }
 
-/**
- * ####################
- * ### 2. Execution ###
- * ####################
- */
+/// A kind inferred by the compiler, as VRL prints it and as a schema
+#[derive(Deserialize, Serialize)]
+pub struct InferredType {
+    pub kind: String,
+    pub schema: TypeSchema,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct InferredTypes {
+    pub event: InferredType,
+    pub metadata: InferredType,
+    pub result: InferredType,
+    pub fallible: bool,
+    pub abortable: bool,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct TypecheckOutput {
+    pub warnings: Vec<CompilationDiagnostic>,
+    pub errors: Vec<CompilationDiagnostic>,
+    /// `None` when the program does not compile
+    pub types: Option<InferredTypes>,
+}
+
+// ####################
+// ### 2. Execution ###
+// ####################
 
 pub type ExecutionEvent = vrl::value::Value;
+pub type ExecutionSecrets = BTreeMap<String, String>;
+
+/// How events, metadata and results are represented, see `codec` for the tagged encoding
+#[derive(Deserialize, Serialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ValueEncoding {
+    #[default]
+    Json,
+    Tagged,
+}
+
+#[derive(Deserialize, Serialize, Default, Clone)]
+#[serde(default)]
+pub struct ExecutionLimits {
+    pub max_duration_ms: Option<f64>,
+    pub max_function_calls: Option<u64>,
+    pub max_output_bytes: Option<usize>,
+    pub max_depth: Option<usize>,
+}
+
+#[derive(Deserialize, Serialize, Default, Clone)]
+#[serde(default)]
+pub struct ExecutionOptions {
+    pub metadata: Option<ExecutionEvent>,
+    pub secrets: Option<ExecutionSecrets>,
+    /// IANA name, "local" or fixed offset, see `timezone::resolve`
+    pub timezone: Option<String>,
+    /// RFC 3339 timestamp returned by `now()` in deterministic programs
+    pub clock: Option<String>,
+    pub sandbox: SandboxOptions,
+    pub limits: ExecutionLimits,
+    /// records the value of every top-level statement, at the cost of compiling them one by one
+    pub trace: bool,
+    pub encoding: ValueEncoding,
+}
+
 pub struct ExecutionInput {
     pub program: String,
     pub event: ExecutionEvent,
+    pub options: ExecutionOptions,
 }
 
 #[derive(Deserialize, Serialize)]
 pub struct SuccessExecutionOutput {
     pub event: ExecutionEvent,
+    pub metadata: ExecutionEvent,
+    pub secrets: ExecutionSecrets,
     pub result: ExecutionEvent,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace: Option<Vec<TraceStep>>,
+}
+
+/// The value of an event or metadata path right after a statement assigned it
+#[derive(Deserialize, Serialize)]
+pub struct TraceAssignment {
+    pub path: String,
+    pub value: Option<ExecutionEvent>,
 }
 
+/// One top-level statement, in execution order: `value` is `None` when it terminated the program
+#[derive(Deserialize, Serialize)]
+pub struct TraceStep {
+    pub span: (usize, usize),
+    pub start: SourcePosition,
+    pub end: SourcePosition,
+    pub value: Option<ExecutionEvent>,
+    pub assignments: Vec<TraceAssignment>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecutionTerminationKind {
+    Abort,
+    Error,
+    Return,
+    Fallible,
+    Missing,
+    Limit,
+}
+
+#[derive(Deserialize, Serialize)]
 pub struct ExecutionTermination {
+    pub kind: ExecutionTerminationKind,
     pub message: String,
+    pub span: Option<(usize, usize)>,
+    pub value: Option<ExecutionEvent>,
+    pub feature: Option<String>,
+    pub labels: Vec<CompilationDiagnosticLabel>,
+    pub notes: Vec<CompilationDiagnosticNote>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace: Option<Vec<TraceStep>>,
+}
+impl ExecutionTermination {
+    pub fn new(kind: ExecutionTerminationKind, message: String) -> Self {
+        ExecutionTermination {
+            kind,
+            message,
+            span: None,
+            value: None,
+            feature: None,
+            labels: vec![],
+            notes: vec![],
+            trace: None,
+        }
+    }
 }
 impl From<&vrl::compiler::runtime::Terminate> for ExecutionTermination {
     fn from(terminate: &vrl::compiler::runtime::Terminate) -> Self {
@@ -258,20 +555,54 @@ impl From<&vrl::compiler::runtime::Terminate> for ExecutionTermination {
             | vrl::compiler::runtime::Terminate::Error(error) => error,
         };
         match expr_error {
-            vrl::prelude::ExpressionError::Abort { message, .. } => ExecutionTermination {
-                message: message.clone().unwrap_or_else(|| "aborted".to_owned()),
-            },
-            vrl::prelude::ExpressionError::Error { message, .. } => ExecutionTermination {
-                message: message.clone(),
+            vrl::prelude::ExpressionError::Abort { span, message } => ExecutionTermination {
+                span: Some((span.start(), span.end())),
+                ..ExecutionTermination::new(
+                    ExecutionTerminationKind::Abort,
+                    message.clone().unwrap_or_else(|| "aborted".to_owned()),
+                )
             },
+            vrl::prelude::ExpressionError::Error {
+                message,
+                labels,
+                notes,
+            } => {
+                let labels = labels
+                    .iter()
+                    .map(CompilationDiagnosticLabel::from)
+                    .collect::<Vec<CompilationDiagnosticLabel>>();
+                ExecutionTermination {
+                    span: labels
+                        .iter()
+                        .find(|label| label.primary)
+                        .map(|label| label.span),
+                    labels,
+                    notes: notes.iter().map(CompilationDiagnosticNote::from).collect(),
+                    ..ExecutionTermination::new(ExecutionTerminationKind::Error, message.clone())
+                }
+            }
             vrl::prelude::ExpressionError::Return { span, value } => ExecutionTermination {
-                message: "return".to_owned(), // TODO: implement this correctly
+                span: Some((span.start(), span.end())),
+                value: Some(value.clone()),
+                ..ExecutionTermination::new(
+                    ExecutionTerminationKind::Return,
+                    "program returned early".to_owned(),
+                )
             },
             vrl::prelude::ExpressionError::Fallible { span } => ExecutionTermination {
-                message: "fallible".to_owned(), // TODO: implement this correctly
+                span: Some((span.start(), span.end())),
+                ..ExecutionTermination::new(
+                    ExecutionTerminationKind::Fallible,
+                    "unhandled fallible expression".to_owned(),
+                )
             },
             vrl::prelude::ExpressionError::Missing { span, feature } => ExecutionTermination {
-                message: "missing".to_owned(), // TODO: implement this correctly
+                span: Some((span.start(), span.end())),
+                feature: Some(feature.to_string()),
+                ..ExecutionTermination::new(
+                    ExecutionTerminationKind::Missing,
+                    format!("missing feature \"{}\"", feature),
+                )
             },
         }
     }
@@ -279,7 +610,12 @@ impl From<&vrl::compiler::runtime::Terminate> for ExecutionTermination {
 
 pub enum ErrorExecutionOutput {
     CompilationError(ErrorCompilationOutput),
-    Termination(ExecutionTermination),
+    /// boxed since terminations are much larger than the other errors
+    Termination(Box<ExecutionTermination>),
+    InvalidOption(String),
+    InvalidInput(String),
+    /// the output could not be serialized
+    Serialization(String),
 }
 impl ErrorExecutionOutput {
     pub fn summary(&self) -> String {
@@ -290,8 +626,312 @@ impl ErrorExecutionOutput {
             ErrorExecutionOutput::Termination(termination) => {
                 format!("Termination;\n{}", termination.message.to_owned())
             }
+            ErrorExecutionOutput::InvalidOption(message) => {
+                format!("Invalid Option;\n{}", message)
+            }
+            ErrorExecutionOutput::InvalidInput(message) => {
+                format!("Invalid Input;\n{}", message)
+            }
+            ErrorExecutionOutput::Serialization(message) => {
+                format!("Serialization Error;\n{}", message)
+            }
         }
     }
 }
 
 pub type ExecutionOutput = Result<SuccessExecutionOutput, ErrorExecutionOutput>;
+
+// ##########################
+// ### 3. Batch Execution ###
+// ##########################
+
+pub struct BatchExecutionInput {
+    pub program: String,
+    pub events: Vec<ExecutionEvent>,
+    pub options: ExecutionOptions,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum BatchExecutionItem {
+    Success(SuccessExecutionOutput),
+    Termination(ExecutionTermination),
+    /// the event or the metadata could not be decoded
+    InvalidInput {
+        message: String,
+    },
+}
+
+pub type BatchExecutionOutput = Result<Vec<BatchExecutionItem>, ErrorExecutionOutput>;
+
+// ##########################
+// ### 4. Language Server ###
+// ##########################
+
+pub struct LanguageInput {
+    pub program: String,
+    pub offset: usize,
+    pub options: CheckOptions,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CompletionItemKind {
+    Function,
+    Variable,
+    Path,
+    Keyword,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct CompletionItem {
+    pub label: String,
+    pub kind: CompletionItemKind,
+    pub detail: Option<String>,
+    pub documentation: Option<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct CompletionOutput {
+    pub span: (usize, usize),
+    pub items: Vec<CompletionItem>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct HoverOutput {
+    pub span: (usize, usize),
+    pub title: String,
+    pub inferred_type: Option<String>,
+    pub documentation: Option<String>,
+}
+
+// #####################
+// ### 5. Formatting ###
+// #####################
+
+#[derive(Deserialize, Serialize)]
+pub struct FormatOutput {
+    /// `None` when the program does not parse
+    pub formatted: Option<String>,
+    pub errors: Vec<CompilationDiagnostic>,
+}
+
+// ##################
+// ### 6. Testing ###
+// ##################
+
+/// Conditions on a termination, a missing condition matches anything
+#[derive(Deserialize, Serialize, Default)]
+#[serde(default)]
+pub struct ExpectedTermination {
+    pub kind: Option<ExecutionTerminationKind>,
+    /// substring of the termination message
+    pub message: Option<String>,
+}
+
+/// One test case: the expectations left out are not checked
+#[derive(Deserialize, Serialize)]
+pub struct TestCase {
+    #[serde(default)]
+    pub name: Option<String>,
+    pub event: ExecutionEvent,
+    #[serde(default)]
+    pub metadata: Option<ExecutionEvent>,
+    #[serde(default)]
+    pub expected_event: Option<ExecutionEvent>,
+    #[serde(default)]
+    pub expected_result: Option<ExecutionEvent>,
+    #[serde(default)]
+    pub expected_termination: Option<ExpectedTermination>,
+}
+
+pub struct TestInput {
+    pub program: String,
+    pub cases: Vec<TestCase>,
+    pub options: ExecutionOptions,
+}
+
+/// A mismatch between the expected and actual value at a path, `None` meaning the value is absent
+#[derive(Deserialize, Serialize)]
+pub struct ValueDifference {
+    /// "event", "result" or "termination"
+    pub target: String,
+    pub path: String,
+    pub expected: Option<ExecutionEvent>,
+    pub actual: Option<ExecutionEvent>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct TestCaseReport {
+    pub name: String,
+    pub passed: bool,
+    pub differences: Vec<ValueDifference>,
+    pub output: Option<SuccessExecutionOutput>,
+    pub termination: Option<ExecutionTermination>,
+    /// why the event or the metadata of the case could not be decoded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub invalid_input: Option<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct TestReport {
+    pub passed: usize,
+    pub failed: usize,
+    pub cases: Vec<TestCaseReport>,
+}
+
+pub type TestOutput = Result<TestReport, ErrorExecutionOutput>;
+
+// ##################
+// ### 7. Routing ###
+// ##################
+
+/// Named VRL conditions, each must resolve to a boolean
+pub type Routes = BTreeMap<String, String>;
+
+#[derive(Deserialize, Serialize)]
+pub struct RouteCompilationError {
+    pub route: String,
+    pub errors: Vec<CompilationDiagnostic>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ErrorRouterOutput {
+    pub routes: Vec<RouteCompilationError>,
+}
+impl ErrorRouterOutput {
+    pub fn summary(&self) -> String {
+        self.routes
+            .iter()
+            .map(|route| {
+                let error_lines = route
+                    .errors
+                    .iter()
+                    .map(|error| error.summary())
+                    .collect::<Vec<String>>();
+                format!("route \"{}\":\n{}", route.route, error_lines.join("\n"))
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct RouteOutput {
+    /// names of the routes whose condition resolved to true, in name order
+    pub matched: Vec<String>,
+    /// conditions that terminated, which do not match
+    pub terminations: BTreeMap<String, ExecutionTermination>,
+}
+
+// ###################
+// ### 8. Analysis ###
+// ###################
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct AccessLocation {
+    pub span: (usize, usize),
+    pub start: SourcePosition,
+    pub end: SourcePosition,
+}
+
+/// A path or secret name as written in the program, with every place it is accessed
+#[derive(Deserialize, Serialize)]
+pub struct AccessedPath {
+    pub path: String,
+    pub locations: Vec<AccessLocation>,
+}
+
+/// Accesses by kind, each sorted by path; `|=` both reads and assigns its target
+#[derive(Deserialize, Serialize, Default)]
+pub struct AccessSet {
+    pub reads: Vec<AccessedPath>,
+    pub assignments: Vec<AccessedPath>,
+    pub deletions: Vec<AccessedPath>,
+}
+
+#[derive(Deserialize, Serialize, Default)]
+pub struct ProgramAnalysis {
+    pub event: AccessSet,
+    pub metadata: AccessSet,
+    /// `get_secret`, `set_secret` and `remove_secret` calls with a literal secret name
+    pub secrets: AccessSet,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct AnalysisOutput {
+    pub warnings: Vec<CompilationDiagnostic>,
+    pub errors: Vec<CompilationDiagnostic>,
+    /// `None` when the program does not compile
+    pub analysis: Option<ProgramAnalysis>,
+}
+
+// ##################
+// ### 9. Caching ###
+// ##################
+
+#[derive(Deserialize, Serialize)]
+pub struct CacheOptions {
+    /// number of compiled programs kept, 0 disables the cache
+    pub capacity: usize,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct CacheStats {
+    pub capacity: usize,
+    pub size: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+// #####################
+// ### 10. Streaming ###
+// #####################
+
+#[derive(Deserialize, Serialize)]
+pub struct TransformLineError {
+    /// 1-based line number since the start of the stream
+    pub line: usize,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub termination: Option<ExecutionTermination>,
+}
+
+#[derive(Deserialize, Serialize, Default)]
+pub struct TransformOutput {
+    /// the resulting events, one JSON document per line, each ending with a newline
+    pub output: String,
+    pub errors: Vec<TransformLineError>,
+}
+impl TransformOutput {
+    /// Adds the outcome of a line, if it was not empty
+    pub fn add(&mut self, line: usize, outcome: Option<Result<String, ErrorExecutionOutput>>) {
+        match outcome {
+            Some(Ok(json)) => {
+                self.output.push_str(&json);
+                self.output.push('\n');
+            }
+            Some(Err(ErrorExecutionOutput::Termination(termination))) => {
+                self.errors.push(TransformLineError {
+                    line,
+                    message: termination.message.clone(),
+                    termination: Some(*termination),
+                })
+            }
+            Some(Err(err)) => {
+                let message = match err {
+                    ErrorExecutionOutput::InvalidOption(message)
+                    | ErrorExecutionOutput::InvalidInput(message)
+                    | ErrorExecutionOutput::Serialization(message) => message,
+                    err => err.summary(),
+                };
+                self.errors.push(TransformLineError {
+                    line,
+                    message,
+                    termination: None,
+                })
+            }
+            None => {}
+        }
+    }
+}
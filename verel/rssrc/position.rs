@@ -0,0 +1,44 @@
+use crate::model;
+
+/**
+ * Converts VRL byte offsets into line/column positions.
+ *
+ * Lines and columns are 1-based, like in Monaco; `column` counts UTF-8 bytes
+ * and `column_utf16` counts UTF-16 code units, which is what JS strings index.
+ */
+pub struct LineIndex<'a> {
+    source: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(source: &'a str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(index, _)| index + 1))
+            .collect();
+        LineIndex {
+            source,
+            line_starts,
+        }
+    }
+
+    pub fn position(&self, offset: usize) -> model::SourcePosition {
+        let mut offset = offset.min(self.source.len());
+        while !self.source.is_char_boundary(offset) {
+            offset -= 1;
+        }
+
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next_line) => next_line - 1,
+        };
+        let line_start = self.line_starts[line];
+        let line_prefix = &self.source[line_start..offset];
+
+        model::SourcePosition {
+            line: line + 1,
+            column: line_prefix.len() + 1,
+            column_utf16: line_prefix.encode_utf16().count() + 1,
+        }
+    }
+}
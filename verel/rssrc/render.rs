@@ -0,0 +1,68 @@
+use crate::model;
+use codespan_reporting::diagnostic::{Diagnostic, Label, LabelStyle, Severity};
+use codespan_reporting::files::SimpleFile;
+use codespan_reporting::term;
+use codespan_reporting::term::termcolor::Buffer;
+
+/**
+ * Renders a diagnostic the same way the VRL CLI does: header with the error code,
+ * the program snippet with line numbers, underlined labels and the notes.
+ */
+pub fn render(
+    diagnostic: &model::CompilationDiagnostic,
+    source: &str,
+    options: &model::FormatDiagnosticOptions,
+) -> Result<String, String> {
+    let file = SimpleFile::new(options.file_name.as_str(), source);
+
+    let severity = match diagnostic.severity.as_str() {
+        "bug" => Severity::Bug,
+        "error" => Severity::Error,
+        "warning" => Severity::Warning,
+        _ => Severity::Note,
+    };
+
+    let labels = diagnostic
+        .labels
+        .iter()
+        .map(|label| {
+            let style = if label.primary {
+                LabelStyle::Primary
+            } else {
+                LabelStyle::Secondary
+            };
+            Label::new(style, (), label.span.0..label.span.1).with_message(&label.message)
+        })
+        .collect();
+
+    let notes = diagnostic.notes.iter().filter_map(render_note).collect();
+
+    let diagnostic = Diagnostic::new(severity)
+        .with_message(&diagnostic.message)
+        .with_code(format!("E{:03}", diagnostic.code))
+        .with_labels(labels)
+        .with_notes(notes);
+
+    let mut buffer = if options.color {
+        Buffer::ansi()
+    } else {
+        Buffer::no_color()
+    };
+    term::emit(&mut buffer, &term::Config::default(), &file, &diagnostic)
+        .map_err(|err| format!("unable to render diagnostic: {}", err))?;
+
+    String::from_utf8(buffer.into_inner())
+        .map_err(|err| format!("unable to render diagnostic: {}", err))
+}
+
+fn render_note(note: &model::CompilationDiagnosticNote) -> Option<String> {
+    match note.note_type.as_str() {
+        "Hint" => Some(format!("hint: {}", note.message)),
+        "Example" => Some(format!("try: {}", note.message)),
+        "CoerceValue" => {
+            Some("hint: coerce the value to the required type using a coercion function".to_owned())
+        }
+        _ if note.message.is_empty() => None,
+        _ => Some(note.message.clone()),
+    }
+}
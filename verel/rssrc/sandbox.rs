@@ -0,0 +1,218 @@
+use crate::determinism;
+use crate::model;
+use vrl::diagnostic::Span;
+use vrl::parser::ast::{
+    Assignment, AssignmentTarget, Container, Expr, FunctionCall, Predicate, QueryTarget, RootExpr,
+    Unary,
+};
+use vrl::prelude::Function;
+
+/// verel's own diagnostic codes start at 1000, VRL uses the codes from 100 to 999
+pub const FORBIDDEN_FUNCTION_CODE: usize = 1000;
+
+/**
+ * Functions that reach outside of the event being processed (environment, host, network,
+ * logs and secrets store); these are not allowed for untrusted programs.
+ */
+const UNTRUSTED_DENIED_FUNCTIONS: &[&str] = &[
+    "get_env_var",
+    "get_hostname",
+    "log",
+    "dns_lookup",
+    "reverse_dns",
+    "get_secret",
+    "set_secret",
+    "remove_secret",
+];
+
+/// Leaves out the functions the sandbox forbids, in case a call was not found by `check`
+pub fn filter(
+    functions: Vec<Box<dyn Function>>,
+    options: &model::SandboxOptions,
+) -> Vec<Box<dyn Function>> {
+    functions
+        .into_iter()
+        .filter(|function| is_allowed(options, function.identifier()))
+        .collect()
+}
+
+/**
+ * Reports the calls to forbidden functions, before compiling the program so the errors name the
+ * forbidden function instead of reporting it as undefined. Programs that do not parse have no
+ * errors here, the compiler reports them.
+ */
+pub fn check(source: &str, options: &model::SandboxOptions) -> Vec<model::CompilationDiagnostic> {
+    let program = match vrl::parser::parse(source) {
+        Ok(program) => program,
+        Err(_) => return vec![],
+    };
+
+    let mut calls = vec![];
+    for root in program.0 {
+        if let RootExpr::Expr(expr) = root.into_inner() {
+            function_calls(expr.into_inner(), &mut calls);
+        }
+    }
+
+    calls
+        .into_iter()
+        .filter_map(|(identifier, span)| {
+            let reason = forbidden_reason(options, &identifier)?;
+            Some(forbidden_call(&identifier, &reason, span))
+        })
+        .collect()
+}
+
+fn forbidden_call(identifier: &str, reason: &str, span: Span) -> model::CompilationDiagnostic {
+    model::CompilationDiagnostic {
+        message: format!("call to forbidden function \"{}\"", identifier),
+        code: FORBIDDEN_FUNCTION_CODE,
+        severity: "error".to_owned(),
+        labels: vec![model::CompilationDiagnosticLabel {
+            message: format!(
+                "function \"{}\" is forbidden because {}",
+                identifier, reason
+            ),
+            primary: true,
+            span: (span.start(), span.end()),
+            start: None,
+            end: None,
+        }],
+        notes: vec![],
+        start: None,
+        end: None,
+    }
+}
+
+/// Collects the identifier and span of every function call in the expression
+fn function_calls(expr: Expr, calls: &mut Vec<(String, Span)>) {
+    match expr {
+        Expr::Literal(_) | Expr::Variable(_) => {}
+        Expr::Container(container) => container_calls(container.into_inner(), calls),
+        Expr::IfStatement(statement) => {
+            let statement = statement.into_inner();
+            match statement.predicate.into_inner() {
+                Predicate::One(expr) => function_calls(expr.into_inner(), calls),
+                Predicate::Many(exprs) => {
+                    for expr in exprs {
+                        function_calls(expr.into_inner(), calls);
+                    }
+                }
+            }
+            let blocks = std::iter::once(statement.if_node).chain(statement.else_node);
+            for block in blocks {
+                for expr in block.into_inner() {
+                    function_calls(expr.into_inner(), calls);
+                }
+            }
+        }
+        Expr::Op(op) => {
+            let op = op.into_inner();
+            function_calls(op.0.into_inner(), calls);
+            function_calls(op.2.into_inner(), calls);
+        }
+        Expr::Assignment(assignment) => match assignment.into_inner() {
+            Assignment::Single { target, expr, .. } => {
+                assignment_target_calls(target.into_inner(), calls);
+                function_calls(expr.into_inner(), calls);
+            }
+            Assignment::Infallible { ok, err, expr, .. } => {
+                assignment_target_calls(ok.into_inner(), calls);
+                assignment_target_calls(err.into_inner(), calls);
+                function_calls(expr.into_inner(), calls);
+            }
+        },
+        Expr::Query(query) => query_target_calls(query.into_inner().target.into_inner(), calls),
+        Expr::FunctionCall(call) => call_calls(call.into_inner(), calls),
+        Expr::Unary(unary) => match unary.into_inner() {
+            Unary::Not(not) => function_calls(not.into_inner().take().1.into_inner(), calls),
+        },
+        Expr::Abort(abort) => {
+            if let Some(message) = abort.into_inner().message {
+                function_calls(message.into_inner(), calls);
+            }
+        }
+        Expr::Return(ret) => function_calls(ret.into_inner().expr.into_inner(), calls),
+    }
+}
+
+fn call_calls(call: FunctionCall, calls: &mut Vec<(String, Span)>) {
+    calls.push((call.ident.inner().to_string(), call.ident.span()));
+    for argument in call.arguments {
+        function_calls(argument.into_inner().expr.into_inner(), calls);
+    }
+    if let Some(closure) = call.closure {
+        for expr in closure.into_inner().block.into_inner() {
+            function_calls(expr.into_inner(), calls);
+        }
+    }
+}
+
+fn container_calls(container: Container, calls: &mut Vec<(String, Span)>) {
+    match container {
+        Container::Group(group) => {
+            function_calls(group.into_inner().into_inner().into_inner(), calls)
+        }
+        Container::Block(block) => {
+            for expr in block.into_inner() {
+                function_calls(expr.into_inner(), calls);
+            }
+        }
+        Container::Array(array) => {
+            for expr in array.into_inner().into_iter() {
+                function_calls(expr.into_inner(), calls);
+            }
+        }
+        Container::Object(object) => {
+            for (_, expr) in object.into_inner().into_iter() {
+                function_calls(expr.into_inner(), calls);
+            }
+        }
+    }
+}
+
+fn query_target_calls(target: QueryTarget, calls: &mut Vec<(String, Span)>) {
+    match target {
+        QueryTarget::FunctionCall(call) => call_calls(call, calls),
+        QueryTarget::Container(container) => container_calls(container, calls),
+        QueryTarget::Internal(_) | QueryTarget::External(_) => {}
+    }
+}
+
+fn assignment_target_calls(target: AssignmentTarget, calls: &mut Vec<(String, Span)>) {
+    if let AssignmentTarget::Query(query) = target {
+        query_target_calls(query.target.into_inner(), calls);
+    }
+}
+pub fn is_allowed(options: &model::SandboxOptions, identifier: &str) -> bool {
+    forbidden_reason(options, identifier).is_none()
+}
+
+fn forbidden_reason(options: &model::SandboxOptions, identifier: &str) -> Option<String> {
+    if let Some(allow) = &options.allow {
+        if !allow.iter().any(|allowed| allowed == identifier) {
+            return Some("it is not in the allow list".to_owned());
+        }
+    }
+
+    if let Some(deny) = &options.deny {
+        if deny.iter().any(|denied| denied == identifier) {
+            return Some("it is in the deny list".to_owned());
+        }
+    }
+
+    if options.deterministic && determinism::NON_DETERMINISTIC_FUNCTIONS.contains(&identifier) {
+        return Some("its result is not deterministic".to_owned());
+    }
+
+    match options.profile {
+        model::SandboxProfile::Full => None,
+        model::SandboxProfile::Untrusted => {
+            if UNTRUSTED_DENIED_FUNCTIONS.contains(&identifier) {
+                Some("it is not available in the \"untrusted\" sandbox profile".to_owned())
+            } else {
+                None
+            }
+        }
+    }
+}
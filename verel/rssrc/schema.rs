@@ -0,0 +1,177 @@
+use crate::model;
+use std::collections::BTreeMap;
+use vrl::compiler::state::{ExternalEnv, LocalEnv};
+use vrl::compiler::TypeState;
+use vrl::value::kind::{Collection, Field, Index};
+use vrl::value::Kind;
+
+/**
+ * Builds the type state a program is checked against from the event and metadata schemas.
+ *
+ * Without a schema, the event can be anything and the metadata is an object of anything,
+ * which is what VRL assumes by default.
+ */
+pub fn type_state(
+    event: Option<&model::TypeSchema>,
+    metadata: Option<&model::TypeSchema>,
+) -> Result<TypeState, String> {
+    let event_kind = match event {
+        Some(schema) => to_kind(schema).map_err(|err| format!("invalid event schema: {}", err))?,
+        None => Kind::any(),
+    };
+    let metadata_kind = match metadata {
+        Some(schema) => {
+            to_kind(schema).map_err(|err| format!("invalid metadata schema: {}", err))?
+        }
+        None => Kind::object(Collection::any()),
+    };
+
+    Ok(TypeState {
+        local: LocalEnv::default(),
+        external: ExternalEnv::new_with_kind(event_kind, metadata_kind),
+    })
+}
+
+pub fn to_kind(schema: &model::TypeSchema) -> Result<Kind, String> {
+    let types = match &schema.schema_type {
+        Some(model::TypeSchemaType::Single(schema_type)) => vec![schema_type.clone()],
+        Some(model::TypeSchemaType::Many(schema_types)) => schema_types.clone(),
+        None if schema.properties.is_some() => vec!["object".to_owned()],
+        None if schema.items.is_some() => vec!["array".to_owned()],
+        None => return Ok(Kind::any()),
+    };
+
+    let mut kind = Kind::never();
+    for schema_type in types {
+        kind = match schema_type.as_str() {
+            "any" => return Ok(Kind::any()),
+            "string" => kind.or_bytes(),
+            "integer" => kind.or_integer(),
+            "float" => kind.or_float(),
+            "number" => kind.or_integer().or_float(),
+            "boolean" => kind.or_boolean(),
+            "null" => kind.or_null(),
+            "timestamp" => kind.or_timestamp(),
+            "regex" => kind.or_regex(),
+            "object" => kind.or_object(object_collection(schema)?),
+            "array" => kind.or_array(array_collection(schema)?),
+            other => return Err(format!("unsupported type \"{}\"", other)),
+        };
+    }
+    Ok(kind)
+}
+
+fn object_collection(schema: &model::TypeSchema) -> Result<Collection<Field>, String> {
+    let required = schema.required.clone().unwrap_or_default();
+
+    let mut known: BTreeMap<Field, Kind> = BTreeMap::new();
+    for (name, property) in schema.properties.iter().flatten() {
+        let property_kind =
+            to_kind(property).map_err(|err| format!("property \"{}\": {}", name, err))?;
+        let property_kind = if required.contains(name) {
+            property_kind
+        } else {
+            property_kind.or_undefined()
+        };
+        known.insert(name.as_str().into(), property_kind);
+    }
+
+    match &schema.additional_properties {
+        None | Some(model::AdditionalProperties::Allowed(true)) => {
+            Ok(Collection::from_parts(known, Kind::any()))
+        }
+        Some(model::AdditionalProperties::Allowed(false)) => Ok(Collection::from(known)),
+        Some(model::AdditionalProperties::Schema(additional)) => {
+            let additional_kind =
+                to_kind(additional).map_err(|err| format!("additionalProperties: {}", err))?;
+            Ok(Collection::from_parts(known, additional_kind))
+        }
+    }
+}
+
+fn array_collection(schema: &model::TypeSchema) -> Result<Collection<Index>, String> {
+    match &schema.items {
+        Some(items) => {
+            let item_kind = to_kind(items).map_err(|err| format!("items: {}", err))?;
+            Ok(Collection::from_unknown(item_kind))
+        }
+        None => Ok(Collection::any()),
+    }
+}
+
+/**
+ * Describes a kind with the same schema format `to_kind` accepts, so the inferred output
+ * of one program can be fed as the event schema of the next one.
+ *
+ * Known array indices are not described: only the kind of the other items is.
+ */
+pub fn from_kind(kind: &Kind) -> model::TypeSchema {
+    if kind.is_any() {
+        return model::TypeSchema {
+            schema_type: Some(model::TypeSchemaType::Single("any".to_owned())),
+            ..Default::default()
+        };
+    }
+
+    let mut schema = model::TypeSchema::default();
+    let mut types = vec![];
+    if kind.contains_bytes() {
+        types.push("string");
+    }
+    if kind.contains_integer() && kind.contains_float() {
+        types.push("number");
+    } else if kind.contains_integer() {
+        types.push("integer");
+    } else if kind.contains_float() {
+        types.push("float");
+    }
+    if kind.contains_boolean() {
+        types.push("boolean");
+    }
+    if kind.contains_null() {
+        types.push("null");
+    }
+    if kind.contains_timestamp() {
+        types.push("timestamp");
+    }
+    if kind.contains_regex() {
+        types.push("regex");
+    }
+    if let Some(fields) = kind.as_object() {
+        types.push("object");
+        let mut properties = BTreeMap::new();
+        let mut required = vec![];
+        for (field, field_kind) in fields.known() {
+            let name = field.to_string();
+            if !field_kind.contains_undefined() {
+                required.push(name.clone());
+            }
+            properties.insert(name, from_kind(&field_kind.without_undefined()));
+        }
+        let unknown_kind = fields.unknown_kind();
+        schema.additional_properties = Some(if unknown_kind.is_any() {
+            model::AdditionalProperties::Allowed(true)
+        } else if unknown_kind.without_undefined().is_never() {
+            model::AdditionalProperties::Allowed(false)
+        } else {
+            model::AdditionalProperties::Schema(Box::new(from_kind(
+                &unknown_kind.without_undefined(),
+            )))
+        });
+        schema.properties = Some(properties);
+        schema.required = Some(required);
+    }
+    if let Some(items) = kind.as_array() {
+        types.push("array");
+        let unknown_kind = items.unknown_kind().without_undefined();
+        if !unknown_kind.is_never() {
+            schema.items = Some(Box::new(from_kind(&unknown_kind)));
+        }
+    }
+
+    schema.schema_type = Some(match types.as_slice() {
+        [single] => model::TypeSchemaType::Single(single.to_string()),
+        _ => model::TypeSchemaType::Many(types.iter().map(|t| t.to_string()).collect()),
+    });
+    schema
+}
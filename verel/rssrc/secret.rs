@@ -0,0 +1,198 @@
+use vrl::prelude::*;
+
+/**
+ * The functions reading and writing the secrets of the execution target. The VRL stdlib
+ * leaves them to the host (Vector defines its own), so verel provides the same ones.
+ */
+pub fn functions() -> Vec<Box<dyn Function>> {
+    vec![
+        Box::new(GetSecret),
+        Box::new(SetSecret),
+        Box::new(RemoveSecret),
+    ]
+}
+
+#[derive(Clone, Copy, Debug)]
+struct GetSecret;
+
+impl Function for GetSecret {
+    fn identifier(&self) -> &'static str {
+        "get_secret"
+    }
+
+    fn usage(&self) -> &'static str {
+        "Returns the value of the given secret, or null when it is not set."
+    }
+
+    fn parameters(&self) -> &'static [Parameter] {
+        &[Parameter {
+            keyword: "key",
+            kind: kind::BYTES,
+            required: true,
+        }]
+    }
+
+    fn examples(&self) -> &'static [Example] {
+        &[Example {
+            title: "get the api key",
+            source: r#"get_secret("api_key")"#,
+            result: Ok("null"),
+        }]
+    }
+
+    fn compile(
+        &self,
+        _state: &TypeState,
+        _ctx: &mut FunctionCompileContext,
+        arguments: ArgumentList,
+    ) -> Compiled {
+        let key = arguments.required("key");
+        Ok(GetSecretFn { key }.as_expr())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct GetSecretFn {
+    key: Box<dyn Expression>,
+}
+
+impl FunctionExpression for GetSecretFn {
+    fn resolve(&self, ctx: &mut Context) -> Resolved {
+        let key = self.key.resolve(ctx)?;
+        let key = key.try_bytes_utf8_lossy()?;
+        Ok(ctx
+            .target()
+            .get_secret(key.as_ref())
+            .map_or(Value::Null, Value::from))
+    }
+
+    fn type_def(&self, _state: &TypeState) -> TypeDef {
+        TypeDef::bytes().add_null().infallible()
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct SetSecret;
+
+impl Function for SetSecret {
+    fn identifier(&self) -> &'static str {
+        "set_secret"
+    }
+
+    fn usage(&self) -> &'static str {
+        "Sets the given secret, returned with the execution output."
+    }
+
+    fn parameters(&self) -> &'static [Parameter] {
+        &[
+            Parameter {
+                keyword: "key",
+                kind: kind::BYTES,
+                required: true,
+            },
+            Parameter {
+                keyword: "secret",
+                kind: kind::BYTES,
+                required: true,
+            },
+        ]
+    }
+
+    fn examples(&self) -> &'static [Example] {
+        &[Example {
+            title: "set the api key",
+            source: r#"set_secret("api_key", "s3cr3t")"#,
+            result: Ok("null"),
+        }]
+    }
+
+    fn compile(
+        &self,
+        _state: &TypeState,
+        _ctx: &mut FunctionCompileContext,
+        arguments: ArgumentList,
+    ) -> Compiled {
+        let key = arguments.required("key");
+        let secret = arguments.required("secret");
+        Ok(SetSecretFn { key, secret }.as_expr())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct SetSecretFn {
+    key: Box<dyn Expression>,
+    secret: Box<dyn Expression>,
+}
+
+impl FunctionExpression for SetSecretFn {
+    fn resolve(&self, ctx: &mut Context) -> Resolved {
+        let key = self.key.resolve(ctx)?;
+        let secret = self.secret.resolve(ctx)?;
+        ctx.target_mut().insert_secret(
+            key.try_bytes_utf8_lossy()?.as_ref(),
+            secret.try_bytes_utf8_lossy()?.as_ref(),
+        );
+        Ok(Value::Null)
+    }
+
+    fn type_def(&self, _state: &TypeState) -> TypeDef {
+        TypeDef::null().infallible()
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct RemoveSecret;
+
+impl Function for RemoveSecret {
+    fn identifier(&self) -> &'static str {
+        "remove_secret"
+    }
+
+    fn usage(&self) -> &'static str {
+        "Removes the given secret."
+    }
+
+    fn parameters(&self) -> &'static [Parameter] {
+        &[Parameter {
+            keyword: "key",
+            kind: kind::BYTES,
+            required: true,
+        }]
+    }
+
+    fn examples(&self) -> &'static [Example] {
+        &[Example {
+            title: "remove the api key",
+            source: r#"remove_secret("api_key")"#,
+            result: Ok("null"),
+        }]
+    }
+
+    fn compile(
+        &self,
+        _state: &TypeState,
+        _ctx: &mut FunctionCompileContext,
+        arguments: ArgumentList,
+    ) -> Compiled {
+        let key = arguments.required("key");
+        Ok(RemoveSecretFn { key }.as_expr())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct RemoveSecretFn {
+    key: Box<dyn Expression>,
+}
+
+impl FunctionExpression for RemoveSecretFn {
+    fn resolve(&self, ctx: &mut Context) -> Resolved {
+        let key = self.key.resolve(ctx)?;
+        ctx.target_mut()
+            .remove_secret(key.try_bytes_utf8_lossy()?.as_ref());
+        Ok(Value::Null)
+    }
+
+    fn type_def(&self, _state: &TypeState) -> TypeDef {
+        TypeDef::null().infallible()
+    }
+}
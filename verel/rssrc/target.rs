@@ -0,0 +1,81 @@
+use crate::model;
+use std::collections::BTreeSet;
+use vrl::compiler::{SecretTarget, Target, TargetValue};
+use vrl::path::OwnedTargetPath;
+use vrl::value::{Secrets, Value};
+
+/**
+ * The target a program runs on: a VRL `TargetValue` that also remembers the keys of its
+ * secrets, since `Secrets` cannot be listed to return them after the execution.
+ */
+#[derive(Debug)]
+pub struct ExecutionTarget {
+    pub value: TargetValue,
+    secret_keys: BTreeSet<String>,
+}
+
+impl ExecutionTarget {
+    pub fn new(event: Value, metadata: Value, secrets: Option<&model::ExecutionSecrets>) -> Self {
+        let mut target = ExecutionTarget {
+            value: TargetValue {
+                value: event,
+                metadata,
+                secrets: Secrets::new(),
+            },
+            secret_keys: BTreeSet::new(),
+        };
+        for (key, secret) in secrets.into_iter().flatten() {
+            target.insert_secret(key, secret);
+        }
+        target
+    }
+
+    /// The secrets as they are after the execution, including the ones set by the program
+    pub fn secrets(&self) -> model::ExecutionSecrets {
+        self.secret_keys
+            .iter()
+            .filter_map(|key| {
+                let secret = self.value.secrets.get(key)?;
+                Some((key.clone(), secret.to_string()))
+            })
+            .collect()
+    }
+}
+
+impl Target for ExecutionTarget {
+    fn target_insert(&mut self, path: &OwnedTargetPath, value: Value) -> Result<(), String> {
+        self.value.target_insert(path, value)
+    }
+
+    fn target_get(&self, path: &OwnedTargetPath) -> Result<Option<&Value>, String> {
+        self.value.target_get(path)
+    }
+
+    fn target_get_mut(&mut self, path: &OwnedTargetPath) -> Result<Option<&mut Value>, String> {
+        self.value.target_get_mut(path)
+    }
+
+    fn target_remove(
+        &mut self,
+        path: &OwnedTargetPath,
+        compact: bool,
+    ) -> Result<Option<Value>, String> {
+        self.value.target_remove(path, compact)
+    }
+}
+
+impl SecretTarget for ExecutionTarget {
+    fn get_secret(&self, key: &str) -> Option<&str> {
+        self.value.get_secret(key)
+    }
+
+    fn insert_secret(&mut self, key: &str, value: &str) {
+        self.secret_keys.insert(key.to_owned());
+        self.value.insert_secret(key, value);
+    }
+
+    fn remove_secret(&mut self, key: &str) {
+        self.secret_keys.remove(key);
+        self.value.remove_secret(key);
+    }
+}
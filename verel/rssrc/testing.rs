@@ -0,0 +1,183 @@
+use crate::model;
+use vrl::value::Value;
+
+/// Compares the outcome of a test case with its expectations
+pub fn evaluate(
+    case: &model::TestCase,
+    name: String,
+    outcome: Result<model::SuccessExecutionOutput, model::ExecutionTermination>,
+) -> model::TestCaseReport {
+    let mut differences = vec![];
+
+    let (output, termination) = match outcome {
+        Ok(output) => {
+            if let Some(expected) = &case.expected_event {
+                diff(
+                    "event",
+                    ".".to_owned(),
+                    expected,
+                    &output.event,
+                    &mut differences,
+                );
+            }
+            if let Some(expected) = &case.expected_result {
+                diff(
+                    "result",
+                    ".".to_owned(),
+                    expected,
+                    &output.result,
+                    &mut differences,
+                );
+            }
+            if let Some(expected) = &case.expected_termination {
+                differences.push(model::ValueDifference {
+                    target: "termination".to_owned(),
+                    path: ".".to_owned(),
+                    expected: Some(
+                        expected
+                            .kind
+                            .map(kind_value)
+                            .unwrap_or_else(|| Value::from("termination")),
+                    ),
+                    actual: None,
+                });
+            }
+            (Some(output), None)
+        }
+        Err(termination) => {
+            match &case.expected_termination {
+                Some(expected) => {
+                    if let Some(kind) = expected.kind {
+                        if kind != termination.kind {
+                            differences.push(model::ValueDifference {
+                                target: "termination".to_owned(),
+                                path: ".kind".to_owned(),
+                                expected: Some(kind_value(kind)),
+                                actual: Some(kind_value(termination.kind)),
+                            });
+                        }
+                    }
+                    if let Some(message) = &expected.message {
+                        if !termination.message.contains(message.as_str()) {
+                            differences.push(model::ValueDifference {
+                                target: "termination".to_owned(),
+                                path: ".message".to_owned(),
+                                expected: Some(Value::from(message.as_str())),
+                                actual: Some(Value::from(termination.message.as_str())),
+                            });
+                        }
+                    }
+                }
+                None => differences.push(model::ValueDifference {
+                    target: "termination".to_owned(),
+                    path: ".".to_owned(),
+                    expected: None,
+                    actual: Some(Value::from(termination.message.as_str())),
+                }),
+            }
+            (None, Some(termination))
+        }
+    };
+
+    model::TestCaseReport {
+        name,
+        passed: differences.is_empty(),
+        differences,
+        output,
+        termination,
+        invalid_input: None,
+    }
+}
+
+/// A test case whose event or metadata could not be decoded, which fails without running
+pub fn invalid_input(name: String, message: String) -> model::TestCaseReport {
+    model::TestCaseReport {
+        name,
+        passed: false,
+        differences: vec![],
+        output: None,
+        termination: None,
+        invalid_input: Some(message),
+    }
+}
+
+fn kind_value(kind: model::ExecutionTerminationKind) -> Value {
+    serde_json::to_value(kind)
+        .ok()
+        .and_then(|kind| kind.as_str().map(Value::from))
+        .unwrap_or(Value::Null)
+}
+
+/// Recursively lists the differences between two values, with paths in VRL syntax
+fn diff(
+    target: &str,
+    path: String,
+    expected: &Value,
+    actual: &Value,
+    differences: &mut Vec<model::ValueDifference>,
+) {
+    let child_path = |segment: String| {
+        if path == "." {
+            format!(".{}", segment.trim_start_matches('.'))
+        } else {
+            format!("{}{}", path, segment)
+        }
+    };
+
+    match (expected, actual) {
+        (Value::Object(expected_fields), Value::Object(actual_fields)) => {
+            let mut keys: Vec<_> = expected_fields.keys().chain(actual_fields.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let key_path = child_path(format!(".{}", field_segment(key.as_ref())));
+                match (expected_fields.get(key), actual_fields.get(key)) {
+                    (Some(expected), Some(actual)) => {
+                        diff(target, key_path, expected, actual, differences)
+                    }
+                    (expected, actual) => differences.push(model::ValueDifference {
+                        target: target.to_owned(),
+                        path: key_path,
+                        expected: expected.cloned(),
+                        actual: actual.cloned(),
+                    }),
+                }
+            }
+        }
+        (Value::Array(expected_items), Value::Array(actual_items)) => {
+            for index in 0..expected_items.len().max(actual_items.len()) {
+                let index_path = child_path(format!("[{}]", index));
+                match (expected_items.get(index), actual_items.get(index)) {
+                    (Some(expected), Some(actual)) => {
+                        diff(target, index_path, expected, actual, differences)
+                    }
+                    (expected, actual) => differences.push(model::ValueDifference {
+                        target: target.to_owned(),
+                        path: index_path,
+                        expected: expected.cloned(),
+                        actual: actual.cloned(),
+                    }),
+                }
+            }
+        }
+        (expected, actual) if expected != actual => differences.push(model::ValueDifference {
+            target: target.to_owned(),
+            path,
+            expected: Some(expected.clone()),
+            actual: Some(actual.clone()),
+        }),
+        _ => {}
+    }
+}
+
+fn field_segment(field: &str) -> String {
+    if !field.is_empty()
+        && field
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '@')
+    {
+        field.to_owned()
+    } else {
+        format!("{:?}", field)
+    }
+}
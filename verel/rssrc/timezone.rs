@@ -0,0 +1,329 @@
+use chrono::format::{parse, Item, Parsed, StrftimeItems};
+use chrono::{DateTime, FixedOffset, Utc};
+use std::cell::Cell;
+use vrl::compiler::conversion::Conversion;
+use vrl::compiler::TimeZone;
+use vrl::prelude::*;
+
+/// The timezone of an execution: a VRL timezone, or a fixed offset VRL cannot represent
+#[derive(Clone, Copy, Debug)]
+pub enum ExecutionTimeZone {
+    Vrl(TimeZone),
+    Fixed(FixedOffset),
+}
+
+impl ExecutionTimeZone {
+    /**
+     * The timezone given to the VRL runtime, read by the stdlib functions verel does not replace
+     * (the log parsing functions and `get_timezone_name`). Whole-hour offsets map to the
+     * equivalent "Etc/GMT" zone; other offsets have none, so those functions use UTC.
+     */
+    pub fn vrl(&self) -> TimeZone {
+        match self {
+            ExecutionTimeZone::Vrl(timezone) => *timezone,
+            ExecutionTimeZone::Fixed(offset) => {
+                let offset_seconds = offset.local_minus_utc();
+                // IANA "Etc/GMT" zones use POSIX signs: "Etc/GMT-2" is two hours east of UTC
+                let etc_name = match offset_seconds / 3600 {
+                    _ if offset_seconds % 3600 != 0 => "Etc/UTC".to_owned(),
+                    0 => "Etc/GMT".to_owned(),
+                    hours => format!("Etc/GMT{:+}", -hours),
+                };
+                TimeZone::parse(&etc_name).unwrap_or_default()
+            }
+        }
+    }
+
+    fn format(&self, timestamp: DateTime<Utc>, items: Vec<Item>) -> String {
+        let items = items.into_iter();
+        match self {
+            ExecutionTimeZone::Vrl(TimeZone::Named(tz)) => timestamp
+                .with_timezone(tz)
+                .format_with_items(items)
+                .to_string(),
+            ExecutionTimeZone::Vrl(TimeZone::Local) => timestamp
+                .with_timezone(&chrono::Local)
+                .format_with_items(items)
+                .to_string(),
+            ExecutionTimeZone::Fixed(offset) => timestamp
+                .with_timezone(offset)
+                .format_with_items(items)
+                .to_string(),
+        }
+    }
+}
+
+/// Parses a date and time without a zone at a fixed offset, failing as VRL's conversion does
+fn parse_at_offset(
+    value: &str,
+    format: &str,
+    offset: FixedOffset,
+) -> Result<DateTime<Utc>, String> {
+    let mut parsed = Parsed::new();
+    parse(&mut parsed, value, StrftimeItems::new(format))
+        .and_then(|()| parsed.to_datetime_with_timezone(&offset))
+        .map(|datetime| datetime.with_timezone(&Utc))
+        .map_err(|err| format!("Invalid timestamp {:?}: {}", value, err))
+}
+
+thread_local! {
+    static CURRENT: Cell<ExecutionTimeZone> =
+        const { Cell::new(ExecutionTimeZone::Vrl(TimeZone::Local)) };
+}
+
+/// Sets the timezone `format_timestamp` and `parse_timestamp` default to, until the next execution
+pub fn set_current(timezone: ExecutionTimeZone) {
+    CURRENT.with(|current| current.set(timezone));
+}
+
+/**
+ * Resolves a timezone option into an execution timezone.
+ *
+ * Accepts "local", any IANA name known to chrono-tz (e.g. "America/Montreal")
+ * or a fixed offset (e.g. "+02:00", "+05:30", "-0500", "UTC+3").
+ */
+pub fn resolve(name: &str) -> Result<ExecutionTimeZone, String> {
+    if let Some(timezone) = TimeZone::parse(name) {
+        return Ok(ExecutionTimeZone::Vrl(timezone));
+    }
+
+    let offset_minutes = parse_offset(name).ok_or_else(|| {
+        format!(
+            "unknown timezone \"{}\"; expected an IANA timezone name (e.g. \"America/Montreal\"), \"local\" or a fixed offset (e.g. \"+02:00\")",
+            name
+        )
+    })?;
+
+    FixedOffset::east_opt(offset_minutes * 60)
+        .map(ExecutionTimeZone::Fixed)
+        .ok_or_else(|| {
+            format!(
+                "unsupported timezone offset \"{}\"; fixed offsets must be less than 24 hours",
+                name
+            )
+        })
+}
+
+fn parse_offset(name: &str) -> Option<i32> {
+    let offset = name
+        .strip_prefix("UTC")
+        .or_else(|| name.strip_prefix("GMT"))
+        .unwrap_or(name);
+
+    let (sign, digits) = if let Some(digits) = offset.strip_prefix('+') {
+        (1, digits)
+    } else if let Some(digits) = offset.strip_prefix('-') {
+        (-1, digits)
+    } else {
+        return None;
+    };
+
+    let digits = digits.replacen(':', "", 1);
+    if digits.is_empty() || digits.len() > 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let (hours, minutes) = if digits.len() <= 2 {
+        (digits.as_str(), "0")
+    } else {
+        digits.split_at(digits.len() - 2)
+    };
+    let hours: i32 = hours.parse().ok()?;
+    let minutes: i32 = minutes.parse().ok()?;
+    if minutes >= 60 {
+        return None;
+    }
+
+    Some(sign * (hours * 60 + minutes))
+}
+
+/**
+ * Replaces `format_timestamp` and `parse_timestamp`, which only know the timezones VRL can
+ * represent (and `format_timestamp` ignores the runtime one), by functions defaulting to the
+ * execution timezone and accepting every timezone `resolve` does as their `timezone` argument.
+ */
+pub fn replace_functions(functions: Vec<Box<dyn Function>>) -> Vec<Box<dyn Function>> {
+    functions
+        .into_iter()
+        .map(|function| match function.identifier() {
+            "format_timestamp" => {
+                Box::new(FormatTimestamp { inner: function }) as Box<dyn Function>
+            }
+            "parse_timestamp" => Box::new(ParseTimestamp { inner: function }) as Box<dyn Function>,
+            _ => function,
+        })
+        .collect()
+}
+
+/// The `timezone` argument when given, the execution timezone otherwise
+fn timezone_argument(
+    timezone: &Option<Box<dyn Expression>>,
+    ctx: &mut Context,
+) -> Result<ExecutionTimeZone, ExpressionError> {
+    match timezone {
+        Some(timezone) => {
+            let timezone = timezone.resolve(ctx)?;
+            let timezone = timezone.try_bytes_utf8_lossy()?;
+            resolve(&timezone).map_err(Into::into)
+        }
+        None => Ok(CURRENT.with(Cell::get)),
+    }
+}
+
+#[derive(Debug)]
+struct FormatTimestamp {
+    inner: Box<dyn Function>,
+}
+
+impl Function for FormatTimestamp {
+    fn identifier(&self) -> &'static str {
+        self.inner.identifier()
+    }
+
+    fn summary(&self) -> &'static str {
+        self.inner.summary()
+    }
+
+    fn usage(&self) -> &'static str {
+        self.inner.usage()
+    }
+
+    fn examples(&self) -> &'static [Example] {
+        self.inner.examples()
+    }
+
+    fn parameters(&self) -> &'static [Parameter] {
+        self.inner.parameters()
+    }
+
+    fn compile(
+        &self,
+        _state: &TypeState,
+        _ctx: &mut FunctionCompileContext,
+        arguments: ArgumentList,
+    ) -> Compiled {
+        Ok(FormatTimestampFn {
+            value: arguments.required("value"),
+            format: arguments.required("format"),
+            timezone: arguments.optional("timezone"),
+        }
+        .as_expr())
+    }
+}
+
+#[derive(Clone, Debug)]
+struct FormatTimestampFn {
+    value: Box<dyn Expression>,
+    format: Box<dyn Expression>,
+    timezone: Option<Box<dyn Expression>>,
+}
+
+impl FunctionExpression for FormatTimestampFn {
+    fn resolve(&self, ctx: &mut Context) -> Resolved {
+        let timestamp = self.value.resolve(ctx)?.try_timestamp()?;
+        let format = self.format.resolve(ctx)?;
+        let format = format.try_bytes_utf8_lossy()?;
+        let items = StrftimeItems::new(&format)
+            .map(|item| match item {
+                Item::Error => Err("invalid format"),
+                _ => Ok(item),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let timezone = timezone_argument(&self.timezone, ctx)?;
+        Ok(timezone.format(timestamp, items).into())
+    }
+
+    fn type_def(&self, _state: &TypeState) -> TypeDef {
+        TypeDef::bytes().fallible()
+    }
+}
+
+#[derive(Debug)]
+struct ParseTimestamp {
+    inner: Box<dyn Function>,
+}
+
+impl Function for ParseTimestamp {
+    fn identifier(&self) -> &'static str {
+        self.inner.identifier()
+    }
+
+    fn summary(&self) -> &'static str {
+        self.inner.summary()
+    }
+
+    fn usage(&self) -> &'static str {
+        self.inner.usage()
+    }
+
+    fn examples(&self) -> &'static [Example] {
+        self.inner.examples()
+    }
+
+    fn parameters(&self) -> &'static [Parameter] {
+        self.inner.parameters()
+    }
+
+    fn compile(
+        &self,
+        _state: &TypeState,
+        _ctx: &mut FunctionCompileContext,
+        arguments: ArgumentList,
+    ) -> Compiled {
+        Ok(ParseTimestampFn {
+            value: arguments.required("value"),
+            format: arguments.required("format"),
+            timezone: arguments.optional("timezone"),
+        }
+        .as_expr())
+    }
+}
+
+/// VRL's conversion with the execution timezone, parsing itself only at the offsets VRL lacks
+#[derive(Clone, Debug)]
+struct ParseTimestampFn {
+    value: Box<dyn Expression>,
+    format: Box<dyn Expression>,
+    timezone: Option<Box<dyn Expression>>,
+}
+
+impl FunctionExpression for ParseTimestampFn {
+    fn resolve(&self, ctx: &mut Context) -> Resolved {
+        let value = self.value.resolve(ctx)?;
+        let value = match value {
+            Value::Bytes(value) => value,
+            Value::Timestamp(_) => return Ok(value),
+            _ => {
+                return Err(
+                    format!("unable to convert {} value to timestamp", value.kind_str()).into(),
+                )
+            }
+        };
+        let format = self.format.resolve(ctx)?;
+        let format = format.try_bytes_utf8_lossy()?;
+
+        let conversion = match timezone_argument(&self.timezone, ctx)? {
+            ExecutionTimeZone::Vrl(timezone) => Conversion::timestamp(&format, timezone),
+            // formats with a zone ignore the timezone, the others need one VRL cannot represent
+            ExecutionTimeZone::Fixed(offset) => {
+                match Conversion::timestamp(&format, TimeZone::default()) {
+                    Conversion::TimestampFmt(..) => {
+                        let value = String::from_utf8_lossy(&value);
+                        return parse_at_offset(&value, &format, offset)
+                            .map(Value::Timestamp)
+                            .map_err(Into::into);
+                    }
+                    conversion => conversion,
+                }
+            }
+        };
+        conversion
+            .convert(value)
+            .map_err(|err| err.to_string().into())
+    }
+
+    fn type_def(&self, _state: &TypeState) -> TypeDef {
+        TypeDef::timestamp().fallible()
+    }
+}
@@ -0,0 +1,74 @@
+use crate::lexer::{self, TokenKind};
+use crate::model;
+use vrl::compiler::TargetValue;
+use vrl::path::PathPrefix;
+
+/**
+ * Splits a program into its top-level statements, as the spans of their source.
+ *
+ * Statements end with a newline or `;` outside of any bracket, unless the line ends with
+ * an operator or the next line starts with `else`. Comments are not part of any statement.
+ */
+pub fn statements(source: &str) -> Vec<(usize, usize)> {
+    let tokens = lexer::tokenize(source);
+    let significant: Vec<_> = tokens.iter().filter(|token| !token.is_trivia()).collect();
+
+    let mut statements = vec![];
+    let mut depth: usize = 0;
+    let mut start: Option<usize> = None;
+
+    for (index, token) in significant.iter().enumerate() {
+        if token.text == ";" && depth == 0 {
+            if let Some(start) = start.take() {
+                statements.push((start, significant[index - 1].span.1));
+            }
+            continue;
+        }
+
+        match token.text {
+            "(" | "[" | "{" => depth += 1,
+            ")" | "]" | "}" => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        start.get_or_insert(token.span.0);
+
+        let next = match significant.get(index + 1) {
+            Some(next) => next,
+            None => break,
+        };
+        let line_break = source[token.span.1..next.span.0].contains('\n');
+        let continued = token.kind == TokenKind::Operator || next.text == "else";
+        if depth == 0 && line_break && !continued {
+            if let Some(start) = start.take() {
+                statements.push((start, token.span.1));
+            }
+        }
+    }
+
+    if let (Some(start), Some(last)) = (start, significant.last()) {
+        statements.push((start, last.span.1));
+    }
+    statements
+}
+
+/// The values of the event and metadata paths a statement assigns, read back from the target
+pub fn assignments(
+    statement: &model::CompiledProgram,
+    target_value: &TargetValue,
+) -> Vec<model::TraceAssignment> {
+    statement
+        .info()
+        .target_assignments
+        .iter()
+        .map(|target_path| {
+            let root = match target_path.prefix {
+                PathPrefix::Event => &target_value.value,
+                PathPrefix::Metadata => &target_value.metadata,
+            };
+            model::TraceAssignment {
+                path: target_path.to_string(),
+                value: root.get(&target_path.path).cloned(),
+            }
+        })
+        .collect()
+}
@@ -12,10 +12,19 @@ const maybeInitialize = () => {
   initialized = true
 }
 
+/** 1-based line and columns; `column` counts UTF-8 bytes and `column_utf16` counts UTF-16 code units */
+export type SourcePosition = {
+  line: number
+  column: number
+  column_utf16: number
+}
+
 export type CompilationDiagnosticLabel = {
   message: string
   primary: boolean
   span: [number, number]
+  start?: SourcePosition
+  end?: SourcePosition
 }
 
 export type CompilationDiagnosticNote = {
@@ -25,24 +34,453 @@ export type CompilationDiagnosticNote = {
 
 export type CompilationDiagnostic = {
   message: string
+  /** VRL codes go from 100 to 999; verel uses 1000 for forbidden functions and 1001 for non-boolean route conditions */
   code: number
   severity: string
   labels: CompilationDiagnosticLabel[]
   notes: CompilationDiagnosticNote[]
+  start?: SourcePosition
+  end?: SourcePosition
+}
+export type SandboxOptions = {
+  profile?: 'full' | 'untrusted'
+  allow?: string[]
+  deny?: string[]
+  /**
+   * forbids random, host and network functions, makes `now()` return the `clock` run option
+   * (then required) and defaults the timezone to UTC
+   */
+  deterministic?: boolean
+}
+export type CompilationOptions = {
+  sandbox?: SandboxOptions
+}
+
+export type TypeSchema = {
+  type?: TypeSchemaType | TypeSchemaType[]
+  properties?: Record<string, TypeSchema>
+  required?: string[]
+  additionalProperties?: boolean | TypeSchema
+  items?: TypeSchema
+}
+export type TypeSchemaType =
+  | 'any'
+  | 'string'
+  | 'integer'
+  | 'float'
+  | 'number'
+  | 'boolean'
+  | 'null'
+  | 'timestamp'
+  | 'regex'
+  | 'object'
+  | 'array'
+
+export type DiagnosticSeverity = 'note' | 'warning' | 'error' | 'bug'
+export type CheckOptions = CompilationOptions & {
+  event_schema?: TypeSchema
+  metadata_schema?: TypeSchema
+  /** warning codes reported as errors */
+  promote?: number[]
+  /** warning codes left out, unless promoted */
+  suppress?: number[]
+  /** warnings below this severity are left out; errors are always reported */
+  min_severity?: DiagnosticSeverity
 }
 export type CheckResult = { warnings: CompilationDiagnostic[]; errors: CompilationDiagnostic[] }
-export const check = (program: string): CheckResult => {
+export const check = (program: string, options: CheckOptions = {}): CheckResult => {
+  maybeInitialize()
+  return withErrors(() => wsm.check(program, options))
+}
+
+export type InferredType = { kind: string; schema: TypeSchema }
+export type TypecheckResult = CheckResult & {
+  /** null when the program does not compile */
+  types: {
+    event: InferredType
+    metadata: InferredType
+    result: InferredType
+    fallible: boolean
+    abortable: boolean
+  } | null
+}
+export const typecheck = (program: string, options: CheckOptions = {}): TypecheckResult => {
+  maybeInitialize()
+  return withErrors(() => wsm.typecheck(program, options))
+}
+
+export type AccessLocation = { span: [number, number]; start: SourcePosition; end: SourcePosition }
+export type AccessedPath = { path: string; locations: AccessLocation[] }
+/** sorted by path; `|=` both reads and assigns its target */
+export type AccessSet = { reads: AccessedPath[]; assignments: AccessedPath[]; deletions: AccessedPath[] }
+export type AnalysisResult = CheckResult & {
+  /** null when the program does not compile */
+  analysis: {
+    event: AccessSet
+    metadata: AccessSet
+    /** only secrets named by a string literal */
+    secrets: AccessSet
+  } | null
+}
+/** Lists the event paths, metadata paths and secrets a program reads, assigns and deletes, without running it */
+export const analyze = (program: string, options: CheckOptions = {}): AnalysisResult => {
+  maybeInitialize()
+  return withErrors(() => wsm.analyze(program, options))
+}
+
+export type TaggedValue =
+  | { $type: 'integer'; value: string }
+  | { $type: 'float'; value: number }
+  | { $type: 'timestamp'; value: string }
+  | { $type: 'bytes'; value: number[] }
+  | { $type: 'regex'; value: string }
+  | { $type: 'object'; value: Record<string, any> }
+
+const MAX_SAFE_BIGINT = BigInt(Number.MAX_SAFE_INTEGER)
+
+/** Encodes bigint, Date, Uint8Array and RegExp values for the "tagged" encoding */
+export const encodeTaggedValue = (value: any): any => {
+  if (typeof value === 'bigint') {
+    const abs = value < 0n ? -value : value
+    return abs > MAX_SAFE_BIGINT ? { $type: 'integer', value: value.toString() } : Number(value)
+  }
+  if (value instanceof Date) {
+    return { $type: 'timestamp', value: value.toISOString() }
+  }
+  if (value instanceof Uint8Array) {
+    return { $type: 'bytes', value: Array.from(value) }
+  }
+  if (value instanceof RegExp) {
+    return { $type: 'regex', value: value.source }
+  }
+  if (Array.isArray(value)) {
+    return value.map(encodeTaggedValue)
+  }
+  if (value !== null && typeof value === 'object') {
+    const fields = Object.fromEntries(Object.entries(value).map(([k, v]) => [k, encodeTaggedValue(v)]))
+    return '$type' in value ? { $type: 'object', value: fields } : fields
+  }
+  return value
+}
+
+/** Decodes a value of the "tagged" encoding: integers beyond 2^53 become bigint, timestamps Date, etc. */
+export const decodeTaggedValue = (value: any): any => {
+  if (Array.isArray(value)) {
+    return value.map(decodeTaggedValue)
+  }
+  if (value === null || typeof value !== 'object') {
+    return value
+  }
+  const tagged = value as TaggedValue
+  switch (tagged.$type) {
+    case 'integer':
+      return BigInt(tagged.value)
+    case 'float':
+      return tagged.value
+    case 'timestamp':
+      return new Date(tagged.value)
+    case 'bytes':
+      return Uint8Array.from(tagged.value)
+    case 'regex':
+      return new RegExp(tagged.value)
+    case 'object':
+      return Object.fromEntries(Object.entries(tagged.value).map(([k, v]) => [k, decodeTaggedValue(v)]))
+    default:
+      return Object.fromEntries(Object.entries(value).map(([k, v]) => [k, decodeTaggedValue(v)]))
+  }
+}
+
+export type RunOptions = {
+  metadata?: Record<string, any>
+  /** read and written by the program with `get_secret`, `set_secret` and `remove_secret` */
+  secrets?: Record<string, string>
+  /** IANA timezone name (e.g. "America/Montreal"), "local" or a fixed offset (e.g. "+02:00", "+05:30") */
+  timezone?: string
+  /** RFC 3339 timestamp returned by `now()` in deterministic programs */
+  clock?: string
+  limits?: ExecutionLimits
+  /** records the value of every top-level statement in `trace` */
+  trace?: boolean
+  /**
+   * "tagged" keeps VRL types that JSON cannot represent. The event, metadata, result and trace
+   * values are returned tagged: pass them to `decodeTaggedValue` to get bigint, Date, Uint8Array
+   * and RegExp values, and build tagged inputs with `encodeTaggedValue`.
+   */
+  encoding?: 'json' | 'tagged'
+}
+export type ExecutionLimits = {
+  /** checked each time the program calls a function or runs a closure iteration */
+  max_duration_ms?: number
+  /** closure iterations count as calls */
+  max_function_calls?: number
+  /** size of the JSON serialized event and result */
+  max_output_bytes?: number
+  max_depth?: number
+}
+export type ExecutionOptions = CompilationOptions & RunOptions
+export type ExecutionResult = {
+  event: any
+  metadata: any
+  secrets: Record<string, string>
+  result: any
+  trace?: TraceStep[]
+}
+export type TraceStep = {
+  span: [number, number]
+  start: SourcePosition
+  end: SourcePosition
+  /** null when the statement terminated the program */
+  value: any
+  assignments: { path: string; value: any }[]
+}
+export const execute = (program: string, event: any, options: ExecutionOptions = {}): ExecutionResult => {
+  maybeInitialize()
+  return withErrors(() => wsm.execute(program, event, options))
+}
+
+export type ExecutionTermination = {
+  kind: 'abort' | 'error' | 'return' | 'fallible' | 'missing' | 'limit'
+  message: string
+  span: [number, number] | null
+  value: any
+  feature: string | null
+  labels: CompilationDiagnosticLabel[]
+  notes: CompilationDiagnosticNote[]
+  trace?: TraceStep[]
+}
+
+export class TerminationError extends Error {
+  public constructor(public readonly termination: ExecutionTermination) {
+    super(termination.message)
+    this.name = 'TerminationError'
+  }
+}
+
+export type VerelErrorKind = 'invalid_input' | 'serialization'
+/** Thrown when the input cannot be converted for VRL, or the output cannot be converted back */
+export class VerelError extends Error {
+  public constructor(public readonly kind: VerelErrorKind, message: string) {
+    super(message)
+    this.name = 'VerelError'
+  }
+}
+
+const withErrors = <T>(fn: () => T): T => {
+  try {
+    return fn()
+  } catch (thrown) {
+    if (thrown instanceof Error && thrown.name === 'TerminationError' && 'termination' in thrown) {
+      throw new TerminationError(thrown.termination as ExecutionTermination)
+    }
+    if (thrown instanceof Error && thrown.name === 'VerelError' && 'kind' in thrown) {
+      throw new VerelError(thrown.kind as VerelErrorKind, thrown.message)
+    }
+    throw thrown
+  }
+}
+export type BatchExecutionItem =
+  | ({ status: 'success' } & ExecutionResult)
+  | ({ status: 'termination' } & ExecutionTermination)
+  /** the event could not be decoded with the tagged encoding */
+  | { status: 'invalid_input'; message: string }
+export const executeBatch = (
+  program: string,
+  events: any[],
+  options: ExecutionOptions = {}
+): BatchExecutionItem[] => {
+  maybeInitialize()
+  return withErrors(() => wsm.execute_batch(program, events, options))
+}
+
+export type TestCase = {
+  name?: string
+  event: any
+  metadata?: Record<string, any>
+  /** expectations left out are not checked */
+  expected_event?: any
+  expected_result?: any
+  expected_termination?: { kind?: ExecutionTermination['kind']; message?: string }
+}
+export type ValueDifference = {
+  target: 'event' | 'result' | 'termination'
+  /** VRL path of the difference, like `.user.tags[1]` */
+  path: string
+  expected: any
+  actual: any
+}
+export type TestCaseReport = {
+  name: string
+  passed: boolean
+  differences: ValueDifference[]
+  output: ExecutionResult | null
+  termination: ExecutionTermination | null
+  /** why the event or metadata of the case could not be decoded with the tagged encoding */
+  invalid_input?: string
+}
+export type TestReport = { passed: number; failed: number; cases: TestCaseReport[] }
+export const runTests = (program: string, cases: TestCase[], options: ExecutionOptions = {}): TestReport => {
   maybeInitialize()
-  return wsm.check(program)
+  return withErrors(() => wsm.run_tests(program, cases, options))
 }
 
-export type ExecutionResult = { event: any; result: any }
-export const execute = (program: string, event: any): ExecutionResult => {
+export type CompiledProgram = {
+  warnings: () => CompilationDiagnostic[]
+  execute: (event: any, options?: RunOptions) => ExecutionResult
+  free: () => void
+}
+export const compile = (program: string, options: CompilationOptions = {}): CompiledProgram => {
   maybeInitialize()
-  return wsm.execute(program, event)
+  const compiled = withErrors(() => wsm.compile(program, options))
+  return {
+    warnings: () => withErrors(() => compiled.warnings()),
+    execute: (event: any, runOptions: RunOptions = {}) =>
+      withErrors(() => compiled.execute(event, runOptions)),
+    free: () => compiled.free()
+  }
 }
 
-export const formatDiagnostic = (diagnostic: CompilationDiagnostic): string => {
+export type RouteResult = {
+  /** names of the routes whose condition is true, in name order */
+  matched: string[]
+  terminations: Record<string, ExecutionTermination>
+}
+export type Router = {
+  route: (event: any, options?: RunOptions) => RouteResult
+  free: () => void
+}
+/** Compiles named boolean conditions, throwing if one does not compile or may not resolve to a boolean */
+export const createRouter = (routes: Record<string, string>, options: CompilationOptions = {}): Router => {
+  maybeInitialize()
+  const router = withErrors(() => wsm.create_router(routes, options))
+  return {
+    route: (event: any, runOptions: RunOptions = {}) => withErrors(() => router.route(event, runOptions)),
+    free: () => router.free()
+  }
+}
+
+export type TransformLineError = {
+  /** 1-based line number since the start of the stream */
+  line: number
+  message: string
+  termination?: ExecutionTermination
+}
+export type TransformResult = {
+  /** the resulting events as newline-delimited JSON */
+  output: string
+  errors: TransformLineError[]
+}
+export type Transformer = {
+  /** transforms the complete lines of the chunk, keeping an incomplete last line for the next one */
+  push: (chunk: Uint8Array | string) => TransformResult
+  /** transforms the last line when the input does not end with a newline */
+  finish: () => TransformResult
+  free: () => void
+}
+/** Compiles a program to run it on newline-delimited JSON pushed in chunks */
+export const createTransformer = (program: string, options: ExecutionOptions = {}): Transformer => {
+  maybeInitialize()
+  const transformer = withErrors(() => wsm.create_transformer(program, options))
+  const encoder = new TextEncoder()
+  return {
+    push: (chunk: Uint8Array | string) =>
+      withErrors(() => transformer.push(typeof chunk === 'string' ? encoder.encode(chunk) : chunk)),
+    finish: () => withErrors(() => transformer.finish()),
+    free: () => transformer.free()
+  }
+}
+
+export type CompletionItem = {
+  label: string
+  kind: 'function' | 'variable' | 'path' | 'keyword'
+  detail: string | null
+  documentation: string | null
+}
+/** `span` is the byte range the selected completion should replace */
+export type CompletionResult = { span: [number, number]; items: CompletionItem[] }
+/** `offset` is a byte offset in the program, like diagnostic spans */
+export const complete = (program: string, offset: number, options: CheckOptions = {}): CompletionResult => {
+  maybeInitialize()
+  return withErrors(() => wsm.complete(program, offset, options))
+}
+
+export type HoverResult = {
+  span: [number, number]
+  title: string
+  inferred_type: string | null
+  documentation: string | null
+}
+export const hover = (program: string, offset: number, options: CheckOptions = {}): HoverResult | null => {
+  maybeInitialize()
+  return withErrors(() => wsm.hover(program, offset, options)) ?? null
+}
+
+export type HostKind =
+  | 'any'
+  | 'string'
+  | 'integer'
+  | 'float'
+  | 'boolean'
+  | 'object'
+  | 'array'
+  | 'timestamp'
+  | 'regex'
+  | 'null'
+export type HostFunctionDefinition = {
+  name: string
+  parameters?: { keyword: string; kind?: HostKind | HostKind[]; required?: boolean }[]
+  return_kind?: HostKind | HostKind[]
+}
+/**
+ * Registers a callback as a VRL function. Host functions are fallible, since the callback may throw
+ * or return a value not matching `return_kind`: call them with "!" or handle their error.
+ */
+export const registerFunction = (definition: HostFunctionDefinition, callback: (...args: any[]) => any): void => {
+  maybeInitialize()
+  withErrors(() => wsm.register_function(definition, callback))
+}
+
+export const unregisterFunction = (name: string): boolean => {
+  maybeInitialize()
+  return wsm.unregister_function(name)
+}
+
+export type FormatResult = {
+  /** null when the program does not parse */
+  formatted: string | null
+  errors: CompilationDiagnostic[]
+}
+export const format = (program: string): FormatResult => {
+  maybeInitialize()
+  return withErrors(() => wsm.format(program))
+}
+
+export type FormatDiagnosticOptions = {
+  /** program the diagnostic was emitted for; when provided, the diagnostic is rendered with source snippets */
+  source?: string
+  /** use ANSI colors in the rendered output */
+  color?: boolean
+  file_name?: string
+}
+export const formatDiagnostic = (diagnostic: CompilationDiagnostic, options: FormatDiagnosticOptions = {}): string => {
+  maybeInitialize()
+  return withErrors(() => wsm.format_diagnostic(diagnostic, options))
+}
+
+export type CacheOptions = {
+  /** number of compiled programs kept by check, typecheck, analyze, execute, executeBatch and runTests; 0 disables the cache */
+  capacity: number
+}
+export type CacheStats = { capacity: number; size: number; hits: number; misses: number }
+export const configureCache = (options: CacheOptions): void => {
+  maybeInitialize()
+  withErrors(() => wsm.configure_cache(options))
+}
+/** Drops the cached programs, keeping the statistics */
+export const clearCache = (): void => {
+  maybeInitialize()
+  wsm.clear_cache()
+}
+export const cacheStats = (): CacheStats => {
   maybeInitialize()
-  return wsm.format_diagnostic(diagnostic)
+  return withErrors(() => wsm.cache_stats())
 }
@@ -0,0 +1,420 @@
+use serde_json::json;
+use verel::{api, model};
+
+fn event(value: serde_json::Value) -> model::ExecutionEvent {
+    serde_json::from_value(value).unwrap()
+}
+
+fn to_json(value: &model::ExecutionEvent) -> serde_json::Value {
+    serde_json::to_value(value).unwrap()
+}
+
+fn execute(
+    program: &str,
+    input: serde_json::Value,
+    options: model::ExecutionOptions,
+) -> model::ExecutionOutput {
+    api::execute(&model::ExecutionInput {
+        program: program.to_owned(),
+        event: event(input),
+        options,
+    })
+}
+
+fn check(program: &str, options: model::CheckOptions) -> model::CheckOutput {
+    api::check(&model::CheckInput {
+        program: program.to_owned(),
+        options,
+    })
+    .unwrap()
+}
+
+#[test]
+fn execute_returns_the_event_and_result() {
+    let output = execute(
+        ".b = to_int!(.a) + 1\n.b * 2",
+        json!({ "a": 1 }),
+        Default::default(),
+    )
+    .ok()
+    .unwrap();
+    assert_eq!(to_json(&output.event), json!({ "a": 1, "b": 2 }));
+    assert_eq!(to_json(&output.result), json!(4));
+}
+
+#[test]
+fn execute_reports_terminations() {
+    match execute(".a = to_int!(.a)", json!({ "a": "x" }), Default::default()) {
+        Err(model::ErrorExecutionOutput::Termination(termination)) => {
+            assert!(termination.message.contains("Invalid integer"));
+            assert_eq!(termination.span, Some((5, 16)));
+        }
+        Err(err) => panic!("unexpected error: {}", err.summary()),
+        Ok(_) => panic!("the program should terminate"),
+    }
+}
+
+#[test]
+fn execute_with_metadata_and_secrets() {
+    let options = model::ExecutionOptions {
+        metadata: Some(event(json!({ "source": "webhook" }))),
+        secrets: Some([("api_key".to_owned(), "s3cr3t".to_owned())].into()),
+        ..Default::default()
+    };
+    let program = ".token = get_secret(\"api_key\")\n%processed_by = \"verel\"\nset_secret(\"rotated\", \"yes\")";
+    let output = execute(program, json!({}), options).ok().unwrap();
+    assert_eq!(to_json(&output.event), json!({ "token": "s3cr3t" }));
+    assert_eq!(
+        to_json(&output.metadata),
+        json!({ "source": "webhook", "processed_by": "verel" })
+    );
+    assert_eq!(
+        output.secrets.get("rotated").map(String::as_str),
+        Some("yes")
+    );
+}
+
+#[test]
+fn execute_in_a_fixed_offset_timezone() {
+    let options = model::ExecutionOptions {
+        timezone: Some("+05:30".to_owned()),
+        ..Default::default()
+    };
+    let program = ".local = format_timestamp!(t'2024-01-01T12:00:00Z', \"%H:%M\")";
+    let output = execute(program, json!({}), options).ok().unwrap();
+    assert_eq!(to_json(&output.event), json!({ "local": "17:30" }));
+}
+
+#[test]
+fn trace_stops_at_return_and_locates_terminations() {
+    let options = model::ExecutionOptions {
+        trace: true,
+        ..Default::default()
+    };
+    let output = execute(".a = 1; return 1; .b = 2", json!({}), options.clone())
+        .ok()
+        .unwrap();
+    assert_eq!(to_json(&output.event), json!({ "a": 1 }));
+    assert_eq!(to_json(&output.result), json!(1));
+    assert_eq!(output.trace.unwrap().len(), 2);
+
+    match execute(".a = 1; abort; .b = 2", json!({}), options) {
+        Err(model::ErrorExecutionOutput::Termination(termination)) => {
+            assert_eq!(termination.span, Some((8, 13)));
+            assert_eq!(termination.trace.unwrap().len(), 2);
+        }
+        Err(err) => panic!("unexpected error: {}", err.summary()),
+        Ok(_) => panic!("the program should abort"),
+    }
+}
+
+#[test]
+fn execute_meters_closure_iterations() {
+    let items: Vec<u32> = (0..1000).collect();
+    let options = model::ExecutionOptions {
+        limits: model::ExecutionLimits {
+            max_function_calls: Some(10),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let program = "for_each(array!(.items)) -> |_index, _item| { null }\n!is_empty(array!(.items))";
+    match execute(program, json!({ "items": items }), options) {
+        Err(model::ErrorExecutionOutput::Termination(termination)) => {
+            assert_eq!(termination.kind, model::ExecutionTerminationKind::Limit);
+        }
+        Err(err) => panic!("unexpected error: {}", err.summary()),
+        Ok(_) => panic!("the program should exceed the limit"),
+    }
+
+    let output = execute(program, json!({ "items": [1] }), Default::default())
+        .ok()
+        .unwrap();
+    assert_eq!(to_json(&output.result), json!(true));
+}
+
+#[test]
+fn parse_timestamp_uses_the_execution_timezone() {
+    let parse = |value: &str, timezone: &str| {
+        let options = model::ExecutionOptions {
+            timezone: Some(timezone.to_owned()),
+            ..Default::default()
+        };
+        let program = format!("parse_timestamp!({:?}, \"%Y-%m-%d %H:%M\")", value);
+        execute(&program, json!({}), options)
+    };
+
+    let paris = parse("2024-01-01 12:00", "Europe/Paris").ok().unwrap();
+    assert_eq!(to_json(&paris.result), json!("2024-01-01T11:00:00Z"));
+    let offset = parse("2024-01-01 12:00", "+05:30").ok().unwrap();
+    assert_eq!(to_json(&offset.result), json!("2024-01-01T06:30:00Z"));
+
+    for timezone in ["Europe/Paris", "+05:30"] {
+        match parse("noon", timezone) {
+            Err(model::ErrorExecutionOutput::Termination(termination)) => assert!(termination
+                .message
+                .contains("Invalid timestamp \"noon\": input contains invalid characters")),
+            Err(err) => panic!("unexpected error: {}", err.summary()),
+            Ok(_) => panic!("the timestamp should not parse"),
+        }
+    }
+}
+
+#[test]
+fn check_reports_forbidden_functions() {
+    let options = model::CheckOptions {
+        sandbox: model::SandboxOptions {
+            profile: model::SandboxProfile::Untrusted,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let output = check(".home = get_env_var!(\"HOME\")", options);
+    assert_eq!(output.errors.len(), 1);
+    assert_eq!(output.errors[0].code, 1000);
+    assert_eq!(
+        output.errors[0].message,
+        "call to forbidden function \"get_env_var\""
+    );
+}
+
+#[test]
+fn analyze_lists_paths_and_secrets() {
+    let output = api::analyze(&model::CheckInput {
+        program:
+            ".level = upcase(string!(.level))\ndel(.password)\ntoken = get_secret(\"api_key\")"
+                .to_owned(),
+        options: Default::default(),
+    })
+    .unwrap();
+    assert!(output.errors.is_empty());
+    let analysis = output.analysis.unwrap();
+    let paths = |accesses: &[model::AccessedPath]| -> Vec<String> {
+        accesses.iter().map(|access| access.path.clone()).collect()
+    };
+    assert_eq!(paths(&analysis.event.reads), vec![".level"]);
+    assert_eq!(paths(&analysis.event.deletions), vec![".password"]);
+    assert_eq!(paths(&analysis.secrets.reads), vec!["api_key"]);
+
+    let output = api::analyze(&model::CheckInput {
+        program: "%tags = {}\n%tags |= { \"a\": 1 }\n.items = map_values(array!(.raw)) -> |item| { del(.x, compact: true); item }\n.ok, .err = parse_json(.message)"
+            .to_owned(),
+        options: Default::default(),
+    })
+    .unwrap();
+    let analysis = output.analysis.unwrap();
+    assert_eq!(paths(&analysis.metadata.reads), vec!["%tags"]);
+    assert_eq!(paths(&analysis.metadata.assignments), vec!["%tags"]);
+    assert_eq!(paths(&analysis.event.reads), vec![".message", ".raw"]);
+    assert_eq!(
+        paths(&analysis.event.assignments),
+        vec![".err", ".items", ".ok"]
+    );
+    assert_eq!(paths(&analysis.event.deletions), vec![".x"]);
+    assert_eq!(analysis.event.deletions[0].locations[0].start.line, 3);
+}
+
+#[test]
+fn check_filters_only_the_listed_warning_codes() {
+    // aborting on an infallible function call warns with 620, an unused variable with 900
+    let program = "unused = 1\n.a = upcase!(\"a\")";
+    let codes = |diagnostics: &[model::CompilationDiagnostic]| -> Vec<usize> {
+        diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.code)
+            .collect()
+    };
+    let mut all = codes(&check(program, Default::default()).warnings);
+    all.sort();
+    assert_eq!(all, vec![620, 900]);
+
+    let promoted = check(
+        program,
+        model::CheckOptions {
+            promote: vec![620],
+            ..Default::default()
+        },
+    );
+    assert_eq!(codes(&promoted.errors), vec![620]);
+    assert_eq!(codes(&promoted.warnings), vec![900]);
+
+    let suppressed = check(
+        program,
+        model::CheckOptions {
+            suppress: vec![620],
+            ..Default::default()
+        },
+    );
+    assert!(suppressed.errors.is_empty());
+    assert_eq!(codes(&suppressed.warnings), vec![900]);
+}
+
+#[test]
+fn deterministic_programs_read_the_clock_option() {
+    let options = model::ExecutionOptions {
+        sandbox: model::SandboxOptions {
+            deterministic: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let clocked = model::ExecutionOptions {
+        clock: Some("2024-03-01T12:00:00Z".to_owned()),
+        ..options.clone()
+    };
+    let output = execute(".at = now()", json!({}), clocked.clone())
+        .ok()
+        .unwrap();
+    assert_eq!(
+        to_json(&output.event),
+        json!({ "at": "2024-03-01T12:00:00Z" })
+    );
+
+    match execute(".at = now()", json!({}), options) {
+        Err(model::ErrorExecutionOutput::InvalidOption(message)) => {
+            assert!(message.contains("\"clock\""))
+        }
+        _ => panic!("deterministic executions should require a clock"),
+    }
+
+    // a compiled program keeps its sandbox, whatever the execution options say
+    let compilation_options = model::CompilationOptions {
+        sandbox: clocked.sandbox.clone(),
+    };
+    let mut program = api::Program::new(
+        ".at = format_timestamp!(now(), \"%H:%M %z\")".to_owned(),
+        &compilation_options,
+    )
+    .ok()
+    .unwrap();
+    let output = program
+        .execute(
+            event(json!({})),
+            &model::ExecutionOptions {
+                clock: clocked.clock,
+                ..Default::default()
+            },
+        )
+        .ok()
+        .unwrap();
+    assert_eq!(to_json(&output.event), json!({ "at": "12:00 +0000" }));
+}
+
+#[test]
+fn invalid_tagged_events_fail_alone() {
+    let options = model::ExecutionOptions {
+        encoding: model::ValueEncoding::Tagged,
+        ..Default::default()
+    };
+    let invalid = json!({ "n": { "$type": "integer", "value": "many" } });
+
+    let items = api::execute_batch(&model::BatchExecutionInput {
+        program: ".n = to_int!(.n) + 1".to_owned(),
+        events: vec![event(json!({ "n": 1 })), event(invalid.clone())],
+        options: options.clone(),
+    })
+    .ok()
+    .unwrap();
+    assert!(matches!(items[0], model::BatchExecutionItem::Success(_)));
+    assert!(matches!(
+        items[1],
+        model::BatchExecutionItem::InvalidInput { .. }
+    ));
+
+    let cases: Vec<model::TestCase> = serde_json::from_value(json!([
+        { "event": invalid, "expected_event": { "n": 2 } },
+        { "event": { "n": 1 }, "expected_event": { "n": 2 } }
+    ]))
+    .unwrap();
+    let report = api::test(&model::TestInput {
+        program: ".n = to_int!(.n) + 1".to_owned(),
+        cases,
+        options,
+    })
+    .ok()
+    .unwrap();
+    assert_eq!((report.passed, report.failed), (1, 1));
+    assert!(report.cases[0].invalid_input.is_some());
+}
+
+#[test]
+fn program_compiles_once_and_runs_many_events() {
+    let mut program = api::Program::new(".n = to_int!(.n) + 1".to_owned(), &Default::default())
+        .ok()
+        .unwrap();
+    for n in 0..3 {
+        let output = program
+            .execute(event(json!({ "n": n })), &Default::default())
+            .ok()
+            .unwrap();
+        assert_eq!(to_json(&output.event), json!({ "n": n + 1 }));
+    }
+}
+
+#[test]
+fn router_matches_conditions_and_names_invalid_routes() {
+    let routes: model::Routes = [
+        ("errors", "(to_int(.status) ?? 0) >= 500"),
+        ("slow", "(to_int(.duration_ms) ?? 0) > 1000"),
+        ("from_api", "starts_with(string!(.source), \"api\")"),
+    ]
+    .into_iter()
+    .map(|(name, condition)| (name.to_owned(), condition.to_owned()))
+    .collect();
+    let mut router = api::Router::new(&routes, &Default::default()).ok().unwrap();
+
+    let output = router
+        .route(
+            &event(json!({ "status": 503, "duration_ms": 20, "source": "api-gateway" })),
+            &Default::default(),
+        )
+        .ok()
+        .unwrap();
+    assert_eq!(output.matched, vec!["errors", "from_api"]);
+
+    let output = router
+        .route(
+            &event(json!({ "status": 200, "duration_ms": 1500, "source": 42 })),
+            &Default::default(),
+        )
+        .ok()
+        .unwrap();
+    assert_eq!(output.matched, vec!["slow"]);
+    assert!(output.terminations.contains_key("from_api"));
+
+    let invalid: model::Routes = [("count".to_owned(), "length!(.items)".to_owned())].into();
+    match api::Router::new(&invalid, &Default::default()) {
+        Err(err) => assert!(err.summary().starts_with("route \"count\":")),
+        Ok(_) => panic!("the route should not compile"),
+    }
+}
+
+#[test]
+fn transformer_runs_chunked_newline_delimited_json() {
+    let mut transformer =
+        api::Transformer::new(".status = to_int!(.status)".to_owned(), Default::default())
+            .ok()
+            .unwrap();
+
+    let first = transformer.push(b"{\"status\":\"200\"}\n{\"sta");
+    assert_eq!(first.output, "{\"status\":200}\n");
+    assert!(first.errors.is_empty());
+
+    let second = transformer.push(b"tus\":\"x\"}\n\nnot json\n{\"status\":\"404\"}");
+    assert_eq!(second.output, "");
+    let lines: Vec<usize> = second.errors.iter().map(|error| error.line).collect();
+    assert_eq!(lines, vec![2, 4]);
+    assert!(second.errors[0].termination.is_some());
+
+    let last = transformer.finish();
+    assert_eq!(last.output, "{\"status\":404}\n");
+}
+
+#[test]
+fn format_pretty_prints_programs() {
+    let output = api::format(".a=1;.b = { \"c\": 2,\n\"d\": 3 }");
+    assert_eq!(
+        output.formatted.as_deref(),
+        Some(".a = 1\n.b = {\n    \"c\": 2,\n    \"d\": 3\n}\n")
+    );
+}
@@ -0,0 +1,67 @@
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn run(name: &str, program: &str, arguments: &[&str], stdin: &str) -> Output {
+    let path = std::env::temp_dir().join(format!("verel-{}-{}.vrl", name, std::process::id()));
+    std::fs::write(&path, program).unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_verel"))
+        .arg(&path)
+        .args(arguments)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    std::fs::remove_file(&path).unwrap();
+    output
+}
+
+#[test]
+fn transforms_events_from_stdin() {
+    let output = run(
+        "transform",
+        ".status = to_int!(.status)",
+        &[],
+        "{\"status\":\"200\"}\n{\"status\":\"x\"}\n{\"status\":\"404\"}",
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "{\"status\":200}\n{\"status\":404}\n"
+    );
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .starts_with("line 2: "));
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn runs_deterministically_with_a_clock() {
+    let output = run(
+        "clock",
+        ".at = now()",
+        &["--clock", "2024-01-01T00:00:00Z"],
+        "{}\n",
+    );
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "{\"at\":\"2024-01-01T00:00:00Z\"}\n"
+    );
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn reports_compilation_errors() {
+    let output = run("invalid", ".a = ", &[], "{}\n");
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("error[E204]"));
+    assert_eq!(output.status.code(), Some(2));
+}
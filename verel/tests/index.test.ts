@@ -1,5 +1,29 @@
 import { expect, test } from 'vitest'
-import { check, execute } from '..'
+import {
+  analyze,
+  cacheStats,
+  check,
+  clearCache,
+  complete,
+  configureCache,
+  decodeTaggedValue,
+  encodeTaggedValue,
+  compile,
+  createRouter,
+  createTransformer,
+  execute,
+  executeBatch,
+  format,
+  formatDiagnostic,
+  hover,
+  registerFunction,
+  runTests,
+  typecheck,
+  unregisterFunction,
+  ExecutionLimits,
+  TerminationError,
+  VerelError
+} from '..'
 
 test('check valid program', () => {
   const validProgram = `
@@ -55,3 +79,521 @@ test('execute camelCase function', () => {
   const result = execute('camelcase!(.key)', inputEvent)
   expect(result.result).toEqual('helloVrl')
 })
+
+test('compile once and execute many events', () => {
+  const program = compile('.count = to_int!(.count) + 1')
+  expect(program.warnings()).toHaveLength(0)
+  expect(program.execute({ count: '1' }).event).toEqual({ count: 2 })
+  expect(program.execute({ count: '41' }).event).toEqual({ count: 42 })
+  program.free()
+})
+
+test('compile invalid program throws', () => {
+  expect(() => compile('del()')).toThrow()
+})
+
+test('execute batch keeps going after a termination', () => {
+  const results = executeBatch('.status = to_int!(.status)', [{ status: '200' }, { status: 'nope' }, { status: '404' }])
+  expect(results.map((r) => r.status)).toEqual(['success', 'termination', 'success'])
+  expect(results[0]).toMatchObject({ event: { status: 200 } })
+  expect(results[2]).toMatchObject({ event: { status: 404 } })
+})
+
+test('execute with metadata and secrets', () => {
+  const program = `
+.token = get_secret("api_key")
+%processed_by = "verel"
+set_secret("rotated", "yes")
+`
+  const result = execute(program, {}, { metadata: { source: 'webhook' }, secrets: { api_key: 's3cr3t' } })
+  expect(result.event).toEqual({ token: 's3cr3t' })
+  expect(result.metadata).toEqual({ source: 'webhook', processed_by: 'verel' })
+  expect(result.secrets).toEqual({ api_key: 's3cr3t', rotated: 'yes' })
+})
+
+test('execute with a configured timezone', () => {
+  const program = '.local = format_timestamp!(t\'2024-01-01T12:00:00Z\', "%H:%M")'
+  expect(execute(program, {}, { timezone: 'America/Montreal' }).event).toEqual({ local: '07:00' })
+  expect(execute(program, {}, { timezone: '+02:00' }).event).toEqual({ local: '14:00' })
+  expect(execute(program, {}, { timezone: '+05:30' }).event).toEqual({ local: '17:30' })
+  const parsed = execute('.at = parse_timestamp!("2024-01-01 12:00", "%Y-%m-%d %H:%M")', {}, { timezone: '+09:30' })
+  expect(parsed.event).toEqual({ at: '2024-01-01T02:30:00Z' })
+  expect(() => execute(program, {}, { timezone: 'Mars/Olympus_Mons' })).toThrow(/unknown timezone/)
+})
+
+test('check against an event schema', () => {
+  const eventSchema = {
+    type: 'object',
+    properties: { count: { type: 'integer' }, name: { type: 'string' } },
+    required: ['count', 'name'],
+    additionalProperties: false
+  } as const
+
+  const untyped = check('.next = .count + 1')
+  expect(untyped.errors).toHaveLength(1)
+
+  const typed = check('.next = .count + 1', { event_schema: eventSchema })
+  expect(typed.errors).toHaveLength(0)
+
+  const mismatch = check('.upper = upcase(.count)', { event_schema: eventSchema })
+  expect(mismatch.errors).toHaveLength(1)
+})
+
+test('typecheck infers the output event and result types', () => {
+  const eventSchema = {
+    type: 'object',
+    properties: { count: { type: 'integer' }, name: { type: 'string' } },
+    required: ['count', 'name'],
+    additionalProperties: false
+  } as const
+
+  const output = typecheck('.total = .count * 2\n.name = upcase(.name)\n.count > 1', { event_schema: eventSchema })
+  expect(output.errors).toHaveLength(0)
+  expect(output.types?.event.schema).toEqual({
+    type: 'object',
+    properties: { count: { type: 'integer' }, name: { type: 'string' }, total: { type: 'integer' } },
+    required: ['count', 'name', 'total'],
+    additionalProperties: false
+  })
+  expect(output.types?.result).toEqual({ kind: 'boolean', schema: { type: 'boolean' } })
+  expect(output.types?.fallible).toBe(false)
+
+  const next = check('.double = .total * 2', { event_schema: output.types?.event.schema })
+  expect(next.errors).toHaveLength(0)
+
+  expect(typecheck('.a = upcase(').types).toBeNull()
+})
+
+test('check forbidden functions in sandbox', () => {
+  const untrusted = check('.env = get_env_var!("HOME")', { sandbox: { profile: 'untrusted' } })
+  expect(untrusted.errors).toHaveLength(1)
+  expect(untrusted.errors[0]?.code).toEqual(1000)
+  expect(untrusted.errors[0]?.message).toContain('get_env_var')
+
+  const allowList = check('.a = upcase!(.a)\n.b = downcase!(.b)', { sandbox: { allow: ['upcase'] } })
+  expect(allowList.errors.map((e) => e.message)).toEqual(['call to forbidden function "downcase"'])
+
+  const denyList = check('.a = upcase!(.a)', { sandbox: { deny: ['upcase'] } })
+  expect(denyList.errors).toHaveLength(1)
+
+  expect(check('.a = upcase!(.a)', { sandbox: { profile: 'untrusted' } }).errors).toHaveLength(0)
+})
+
+test('execute host function', () => {
+  const userIds: Record<string, string> = { alice: 'usr_1' }
+  registerFunction(
+    {
+      name: 'lookup_user_id',
+      parameters: [{ keyword: 'name', kind: 'string', required: true }],
+      return_kind: ['string', 'null']
+    },
+    (name: string) => userIds[name] ?? null
+  )
+
+  try {
+    expect(execute('.user_id = lookup_user_id!(.name)', { name: 'alice' }).event).toEqual({
+      name: 'alice',
+      user_id: 'usr_1'
+    })
+    expect(check('.user_id = lookup_user_id(.name)').errors).toHaveLength(1)
+  } finally {
+    expect(unregisterFunction('lookup_user_id')).toBe(true)
+  }
+
+  expect(check('.user_id = lookup_user_id!(.name)').errors).toHaveLength(1)
+})
+
+test('host function values must match the declared return kind', () => {
+  registerFunction({ name: 'user_count', parameters: [], return_kind: 'integer' }, () => 'many')
+  try {
+    expect(() => execute('.count = user_count!()', {})).toThrow(/returned string but declares integer/)
+    expect(execute('.count = user_count() ?? 0', {}).event).toEqual({ count: 0 })
+  } finally {
+    unregisterFunction('user_count')
+  }
+})
+
+test('execute throws structured termination errors', () => {
+  const program = '.a = 1\nabort "stop here"'
+  let thrown: unknown
+  try {
+    execute(program, {})
+  } catch (err) {
+    thrown = err
+  }
+  expect(thrown).toBeInstanceOf(TerminationError)
+  const { termination } = thrown as TerminationError
+  expect(termination.kind).toEqual('abort')
+  expect(termination.message).toEqual('stop here')
+  expect(termination.span).not.toBeNull()
+  const [start, end] = termination.span!
+  expect(program.slice(start, end)).toContain('abort')
+
+  const error = executeBatch('.a = to_int!(.a)', [{ a: 'x' }])[0]
+  expect(error).toMatchObject({ status: 'termination', kind: 'error' })
+})
+
+test('execute enforces resource limits', () => {
+  const program = `
+.words = map_values(.items) -> |item| { upcase!(item) }
+`
+  const items = Array.from({ length: 100 }, (_, i) => `item${i}`)
+  const run = (limits: ExecutionLimits) => {
+    try {
+      execute(program, { items }, { limits })
+      return null
+    } catch (err) {
+      return (err as TerminationError).termination
+    }
+  }
+
+  expect(run({ max_function_calls: 1000 })).toBeNull()
+  expect(run({ max_function_calls: 10 })).toMatchObject({ kind: 'limit' })
+  expect(run({ max_output_bytes: 100 })).toMatchObject({ kind: 'limit' })
+  expect(run({ max_depth: 1 })).toMatchObject({ kind: 'limit' })
+  expect(run({ max_depth: 2 })).toBeNull()
+})
+
+test('execute meters closure iterations', () => {
+  const items = Array.from({ length: 1000 }, (_, i) => i)
+  let thrown: unknown
+  try {
+    execute('for_each(array!(.items)) -> |_index, _item| { null }', { items }, { limits: { max_function_calls: 10 } })
+  } catch (err) {
+    thrown = err
+  }
+  expect(thrown).toBeInstanceOf(TerminationError)
+  expect((thrown as TerminationError).termination).toMatchObject({ kind: 'limit' })
+})
+
+test('format diagnostic with source snippet', () => {
+  const program = '.a = 1\ndel()'
+  const [error] = check(program).errors
+  expect(error).toBeDefined()
+
+  const plain = formatDiagnostic(error!, { source: program })
+  expect(plain).toContain(`error[E${String(error!.code).padStart(3, '0')}]`)
+  expect(plain).toContain('program.vrl:2:1')
+  expect(plain).toContain('del()')
+  expect(plain).not.toContain('\u001b[')
+
+  const colored = formatDiagnostic(error!, { source: program, color: true })
+  expect(colored).toContain('\u001b[')
+
+  expect(formatDiagnostic(error!)).toContain('message: ')
+})
+
+test('check diagnostics have line and column positions', () => {
+  const program = '.a = 1\n.greeting = "héllo 👋"; .x = del()'
+  const [error] = check(program).errors
+  expect(error?.start).toEqual({ line: 2, column: 33, column_utf16: 30 })
+
+  const label = error!.labels.find((l) => l.primary)!
+  expect(label.start).toEqual(error!.start)
+  expect(label.end?.line).toEqual(2)
+})
+
+test('complete functions, variables and event paths', () => {
+  const eventSchema = { type: 'object', properties: { user: { type: 'object', properties: { name: { type: 'string' } } } } } as const
+
+  const functions = complete('.a = upc', 8)
+  expect(functions.span).toEqual([5, 8])
+  expect(functions.items.map((i) => i.label)).toContain('upcase')
+  expect(functions.items.find((i) => i.label === 'upcase')?.detail).toMatch(/^upcase\(value: /)
+
+  const variables = complete('status_code = 200\n.b = stat', 27)
+  expect(variables.items).toContainEqual(expect.objectContaining({ label: 'status_code', kind: 'variable' }))
+
+  const paths = complete('.x = .us', 8, { event_schema: eventSchema })
+  expect(paths.items.map((i) => i.label)).toEqual(['.user', '.user.name'])
+})
+
+test('hover functions and event paths', () => {
+  const eventSchema = { type: 'object', properties: { name: { type: 'string' } }, required: ['name'] } as const
+  const program = '.upper = upcase(.name)'
+
+  const fn = hover(program, 10)
+  expect(fn?.title).toMatch(/^upcase\(/)
+  expect(fn?.documentation).toBeTruthy()
+
+  const path = hover(program, 18, { event_schema: eventSchema })
+  expect(path).toMatchObject({ title: '.name', inferred_type: 'string' })
+
+  expect(hover(program, 7)).toBeNull()
+
+  // the token starting at the offset wins over the one ending there
+  expect(hover(program, 9)?.title).toMatch(/^upcase\(/)
+  expect(hover(program, 16, { event_schema: eventSchema })?.title).toEqual('.name')
+})
+
+test('hover local variables with their inferred type', () => {
+  const program = 'count = length!(.items)\n.total = count'
+  expect(hover(program, 33)).toMatchObject({ title: 'count', inferred_type: 'integer' })
+})
+
+test('format a badly indented program', () => {
+  const program = '# status\nif .code>=200&&.code<300 {\n.ok=true ; .n = to_int!( .n )+1\n}   else{ .ok = false }'
+  const { formatted, errors } = format(program)
+  expect(errors).toHaveLength(0)
+  expect(formatted).toEqual(
+    '# status\nif .code >= 200 && .code < 300 {\n    .ok = true\n    .n = to_int!(.n) + 1\n} else {\n    .ok = false\n}\n'
+  )
+  expect(format(formatted!).formatted).toEqual(formatted)
+
+  expect(format('.a = { "b": 1,\n"c": 2 }').formatted).toEqual('.a = {\n    "b": 1,\n    "c": 2\n}\n')
+  expect(format('.a = {"b":1, "c":2}').formatted).toEqual('.a = { "b": 1, "c": 2 }\n')
+
+  const invalid = format('.a = upcase(')
+  expect(invalid.formatted).toBeNull()
+  expect(invalid.errors[0]?.start?.line).toEqual(1)
+})
+
+test('run test cases against a program', () => {
+  const program = 'if .name == "boom" { abort }\n.greeting = "hello " + string!(.name)\n.greeting'
+  const report = runTests(program, [
+    { event: { name: 'ada' }, expected_event: { name: 'ada', greeting: 'hello ada' }, expected_result: 'hello ada' },
+    {
+      name: 'wrong expectations',
+      event: { name: 'bob', tags: ['a'] },
+      expected_event: { name: 'bob', greeting: 'hi bob', tags: ['a', 'b'] }
+    },
+    { event: { name: 'boom' }, expected_termination: { kind: 'abort' } }
+  ])
+
+  expect(report.passed).toEqual(2)
+  expect(report.failed).toEqual(1)
+  expect(report.cases.map((c) => c.name)).toEqual(['case 1', 'wrong expectations', 'case 3'])
+  expect(report.cases[1]?.differences).toEqual([
+    { target: 'event', path: '.greeting', expected: 'hi bob', actual: 'hello bob' },
+    { target: 'event', path: '.tags[1]', expected: 'b', actual: null }
+  ])
+})
+
+test('trace the top-level statements', () => {
+  const program = '# greet\nname = upcase!(.name)\n.greeting = "hello " + name; .count = 1\nif .count > 0 {\n  .positive = true\n}'
+  const { event, trace } = execute(program, { name: 'ada' }, { trace: true })
+
+  expect(event).toEqual({ name: 'ada', greeting: 'hello ADA', count: 1, positive: true })
+  expect(trace?.map((step) => program.slice(...step.span))).toEqual([
+    'name = upcase!(.name)',
+    '.greeting = "hello " + name',
+    '.count = 1',
+    'if .count > 0 {\n  .positive = true\n}'
+  ])
+  expect(trace?.[0]?.value).toEqual('ADA')
+  expect(trace?.[1]?.assignments).toEqual([{ path: '.greeting', value: 'hello ADA' }])
+  expect(trace?.[3]?.start).toEqual({ line: 4, column: 1, column_utf16: 1 })
+
+  expect(execute(program, { name: 'ada' }).trace).toBeUndefined()
+})
+
+test('trace stops at return and abort', () => {
+  const returned = execute('.a = 1; return 1; .b = 2', {}, { trace: true })
+  expect(returned.event).toEqual({ a: 1 })
+  expect(returned.result).toEqual(1)
+  expect(returned.trace).toHaveLength(2)
+
+  const program = '.a = 1\nabort "stop here"\n.b = 2'
+  let thrown: unknown
+  try {
+    execute(program, {}, { trace: true })
+  } catch (err) {
+    thrown = err
+  }
+  const { termination } = thrown as TerminationError
+  expect(termination.trace).toHaveLength(2)
+  const [start, end] = termination.span!
+  expect(program.slice(start, end)).toContain('abort')
+})
+
+test('round trip VRL types with the tagged encoding', () => {
+  const id = 2n ** 60n + 1n
+  const at = new Date('2024-01-02T03:04:05.678Z')
+  const event = {
+    ...encodeTaggedValue({ id, at, raw: new Uint8Array([255, 0]), spec: { $type: 'custom' } }),
+    ratio: { $type: 'float', value: 2 }
+  }
+  const program = '.next = .id + 1\n.types = [is_timestamp(.at), is_float(.ratio), is_object(.spec)]'
+  const output = execute(program, event, { encoding: 'tagged' })
+
+  expect(output.event.next).toEqual({ $type: 'integer', value: (id + 1n).toString() })
+  expect(output.event.types).toEqual([true, true, true])
+  expect(output.event.at).toEqual({ $type: 'timestamp', value: '2024-01-02T03:04:05.678Z' })
+  expect(output.event.ratio).toEqual({ $type: 'float', value: 2 })
+  expect(output.event.spec).toEqual({ $type: 'object', value: { $type: 'custom' } })
+
+  const decoded = decodeTaggedValue(output.event)
+  expect(decoded.next).toEqual(id + 1n)
+  expect(decoded.at).toEqual(at)
+  expect(decoded.raw).toEqual(new Uint8Array([255, 0]))
+  expect(decoded.spec).toEqual({ $type: 'custom' })
+
+  const invalid = { at: { $type: 'timestamp', value: 'yesterday' } }
+  expect(() => execute('.', invalid, { encoding: 'tagged' })).toThrow(/Invalid Input/)
+  expect(executeBatch('.', [{ a: 1 }, invalid], { encoding: 'tagged' })).toMatchObject([
+    { status: 'success', event: { a: 1 } },
+    { status: 'invalid_input', message: expect.stringMatching(/^event /) }
+  ])
+})
+
+test('invalid inputs throw structured errors instead of panicking', () => {
+  const errorKind = (fn: () => unknown) => {
+    try {
+      fn()
+    } catch (thrown) {
+      return thrown instanceof VerelError ? thrown.kind : 'other'
+    }
+    return 'none'
+  }
+
+  const cyclic: any = { name: 'loop' }
+  cyclic.self = cyclic
+  expect(errorKind(() => execute('.', cyclic))).toEqual('invalid_input')
+  expect(errorKind(() => execute('.', { big: 1n }))).toEqual('invalid_input')
+  expect(errorKind(() => executeBatch('.', 'not a list' as any))).toEqual('invalid_input')
+  expect(errorKind(() => execute('.', {}, { limits: { max_function_calls: 'many' as any } }))).toEqual('invalid_input')
+  expect(errorKind(() => compile('.').execute([cyclic]))).toEqual('invalid_input')
+  expect(errorKind(() => formatDiagnostic({ message: 1 } as any))).toEqual('invalid_input')
+
+  expect(execute('.', { a: undefined, b: 1 }).event).toEqual({ b: 1 })
+
+  registerFunction({ name: 'cyclic_value', parameters: [], return_kind: 'any' }, () => cyclic)
+  try {
+    expect(() => execute('.value = cyclic_value!()', {})).toThrow(TerminationError)
+  } finally {
+    unregisterFunction('cyclic_value')
+  }
+
+  expect(execute('.ok = true', {}).event).toEqual({ ok: true })
+})
+
+test('route events with named boolean conditions', () => {
+  const router = createRouter({
+    errors: '(to_int(.status) ?? 0) >= 500',
+    slow: '(to_int(.duration_ms) ?? 0) > 1000',
+    from_api: 'starts_with(string!(.source), "api")'
+  })
+
+  expect(router.route({ status: 503, duration_ms: 20, source: 'api-gateway' }).matched).toEqual(['errors', 'from_api'])
+  expect(router.route({ status: 200, duration_ms: 1500, source: 'worker' }).matched).toEqual(['slow'])
+
+  const terminated = router.route({ status: 200, duration_ms: 1, source: 42 })
+  expect(terminated.matched).toEqual([])
+  expect(Object.keys(terminated.terminations)).toEqual(['from_api'])
+  router.free()
+
+  expect(() => createRouter({ valid: '.a == 1', count: 'length!(.items)' })).toThrow(/route "count"/)
+})
+
+test('analyze the paths a program reads, assigns and deletes', () => {
+  const { analysis, errors } = analyze(`
+.level = upcase(string!(.level))
+del(.password)
+%source = "api"
+parsed, err = parse_json(.message)
+token = get_secret("api_key")
+`)
+  expect(errors).toEqual([])
+
+  const paths = (accesses: { path: string }[]) => accesses.map(access => access.path)
+  expect(paths(analysis!.event.reads)).toEqual(['.level', '.message'])
+  expect(paths(analysis!.event.assignments)).toEqual(['.level'])
+  expect(paths(analysis!.event.deletions)).toEqual(['.password'])
+  expect(paths(analysis!.metadata.assignments)).toEqual(['%source'])
+  expect(paths(analysis!.secrets.reads)).toEqual(['api_key'])
+
+  const [password] = analysis!.event.deletions[0].locations
+  expect(password.start).toEqual({ line: 3, column: 5, column_utf16: 5 })
+
+  expect(analyze('.a = ').analysis).toBeNull()
+})
+
+test('cache compiled programs by source and options', () => {
+  clearCache()
+  const program = `.cached = true`
+  const before = cacheStats()
+
+  execute(program, {})
+  execute(program, { a: 1 })
+  check(program)
+  execute(program, {}, { sandbox: { profile: 'untrusted' } })
+
+  const after = cacheStats()
+  expect(after.misses - before.misses).toBe(3)
+  expect(after.hits - before.hits).toBe(1)
+
+  configureCache({ capacity: 1 })
+  expect(cacheStats().size).toBe(1)
+  configureCache({ capacity: 64 })
+})
+
+test('execute deterministically with a fixed clock', () => {
+  const options = { sandbox: { deterministic: true }, clock: '2024-03-01T12:00:00Z' }
+  const program = `.processed_at = format_timestamp!(now(), "%Y-%m-%d %H:%M")`
+
+  const first = execute(program, {}, options)
+  expect(first.event).toEqual({ processed_at: '2024-03-01 12:00' })
+  expect(execute(program, {}, options)).toEqual(first)
+
+  const [error] = check('.id = uuid_v4()', { sandbox: { deterministic: true } }).errors
+  expect(error.code).toBe(1000)
+  expect(error.labels[0].message).toMatch(/not deterministic/)
+
+  expect(() => execute('now()', {}, { sandbox: { deterministic: true } })).toThrow(/require the "clock"/)
+  const compiled = compile('.at = format_timestamp!(now(), "%H:%M %z")', { sandbox: { deterministic: true } })
+  // the timezone defaults to UTC for the compiled program too, whatever the run options
+  expect(compiled.execute({}, { clock: options.clock }).event).toEqual({ at: '12:00 +0000' })
+  compiled.free()
+
+  const [authorization] = check('parse_linux_authorization!(.message)', { sandbox: { deterministic: true } }).errors
+  expect(authorization.code).toBe(1000)
+
+  const [syslog] = check('parse_syslog!(.message)', { sandbox: { deterministic: true } }).errors
+  expect(syslog.code).toBe(1000)
+})
+
+test('check promotes, suppresses and filters warnings by code and severity', () => {
+  // aborting on an infallible function call is a warning
+  const program = `.a = upcase!("a")`
+  const [warning] = check(program).warnings
+  expect(warning.severity).toBe('warning')
+
+  const promoted = check(program, { promote: [warning.code] })
+  expect(promoted.warnings).toHaveLength(0)
+  expect(promoted.errors).toMatchObject([{ code: warning.code, severity: 'error' }])
+
+  expect(check(program, { suppress: [warning.code] })).toEqual({ warnings: [], errors: [] })
+  expect(check(program, { min_severity: 'error' })).toEqual({ warnings: [], errors: [] })
+  expect(check(program, { min_severity: 'warning' }).warnings).toHaveLength(1)
+
+  // only the listed codes are promoted or suppressed, the unused variable warning stays
+  const both = `unused = 1\n${program}`
+  const codes = (diagnostics: { code: number }[]) => diagnostics.map((diagnostic) => diagnostic.code)
+  expect(codes(check(both).warnings)).toHaveLength(2)
+  const promotedOne = check(both, { promote: [warning.code] })
+  expect(codes(promotedOne.errors)).toEqual([warning.code])
+  expect(codes(promotedOne.warnings)).not.toContain(warning.code)
+  expect(codes(promotedOne.warnings)).toHaveLength(1)
+  const suppressedOne = check(both, { suppress: [warning.code] })
+  expect(codes(suppressedOne.warnings)).not.toContain(warning.code)
+  expect(codes(suppressedOne.warnings)).toHaveLength(1)
+})
+
+test('transform newline-delimited JSON pushed in chunks', () => {
+  const transformer = createTransformer('.count = to_int!(.count) + 1')
+
+  const first = transformer.push('{"count": 1}\n{"cou')
+  expect(first).toEqual({ output: '{"count":2}\n', errors: [] })
+
+  const second = transformer.push(new TextEncoder().encode('nt": 41}\n\nnot json\n{"count": "x"}'))
+  expect(second.output).toBe('{"count":42}\n')
+  expect(second.errors).toEqual([{ line: 4, message: expect.stringMatching(/invalid JSON/) }])
+
+  const last = transformer.finish()
+  expect(last.output).toBe('')
+  expect(last.errors).toMatchObject([{ line: 5 }])
+  expect(last.errors[0].termination).toBeDefined()
+  transformer.free()
+
+  expect(() => createTransformer('.a = ')).toThrow()
+})